@@ -1,36 +1,1071 @@
-use std::time::Duration;
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use serde::Deserialize;
-use serde_valid::Validate;
+use anyhow::anyhow;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_valid::{Validate, validation::Error};
+use serde_yaml::Value;
 
-#[derive(Debug, Clone, Deserialize, Validate)]
+use crate::diagnostics::{Diagnostic, Diagnostics, Problem, Segment, suggest};
+use crate::error::FlokConfigError;
+use crate::utils::{
+    file_watcher::WatchFilter, format::ConfigFormat, interpolate::interpolate, merge::deep_merge,
+};
+
+/// Names of the config file, in the order they are looked up in each directory
+pub const CONFIG_FILE_NAMES: [&str; 5] = [
+    "flok.yaml",
+    "flok.yml",
+    ".flok.yaml",
+    "flok.toml",
+    "flok.json",
+];
+
+/// Looks for a config file in `dir` and then in its parents, stopping at the git root or the
+/// filesystem root
+pub fn find_config_file(dir: &Path) -> Option<PathBuf> {
+    for dir in dir.ancestors() {
+        let config_file = CONFIG_FILE_NAMES
+            .iter()
+            .map(|x| dir.join(x))
+            .find(|x| x.is_file());
+        if config_file.is_some() {
+            return config_file;
+        }
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+    None
+}
+
+/// Names of the personal override file, looked up next to the config file
+pub const OVERRIDE_FILE_NAMES: [&str; 4] = [
+    "flok.override.yaml",
+    "flok.override.yml",
+    "flok.override.toml",
+    "flok.override.json",
+];
+
+/// Environment variables to set, where a null value unsets the variable instead
+pub type EnvConfig = BTreeMap<String, Option<String>>;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Validate, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct AppConfig {
+    #[serde(default)]
     #[validate(min_items = 1)]
+    #[validate]
     pub flocks: Vec<FlockConfig>,
     #[serde(default)]
+    #[validate]
     pub processes: Vec<ProcessConfig>,
+    /// Environment of every process, overridden by the environment of flocks and processes
+    #[serde(default, deserialize_with = "deserialize_env")]
+    #[schemars(with = "BTreeMap<String, Option<EnvValue>>")]
+    pub env: EnvConfig,
+    /// Dotenv files relative to the config file, overridden by `env`
+    #[serde(default)]
+    #[validate(custom = validate_env_files)]
+    pub env_file: Vec<PathBuf>,
+    /// Other config files whose processes and flocks are merged into this config, as paths or
+    /// globs relative to this config file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Directory of the config file, set when the config is resolved
+    #[serde(skip)]
+    pub project_root: PathBuf,
+    /// Files the config is read from, including env files, along with the override files that
+    /// might be created next to the config file, so that the config can be reloaded when any of
+    /// them changes
+    #[serde(skip)]
+    pub watched_files: Vec<PathBuf>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+impl AppConfig {
+    /// Reads the config file, along with the files it includes and the local override file next
+    /// to it
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let mut config = read_config_file(path)?;
+        let mut loaded = vec![path.canonicalize()?];
+        config.merge_includes(path, &mut loaded)?;
+
+        let dir = loaded[0].parent().unwrap_or(Path::new("/")).to_path_buf();
+        let override_paths: Vec<_> = OVERRIDE_FILE_NAMES.iter().map(|x| dir.join(x)).collect();
+        if let Some(override_path) = override_paths.iter().find(|x| x.is_file()) {
+            config.merge_override(override_path)?;
+        }
+
+        loaded.extend(override_paths);
+        config.watched_files = loaded;
+        Ok(config)
+    }
+
+    /// Loads, resolves and validates the config file at `path`, ready for the processes to run
+    pub fn from_file(path: &Path) -> Result<Self, FlokConfigError> {
+        let mut config = AppConfig::load(path)?;
+        config.resolve(path)?;
+        config
+            .check(path)
+            .map_err(FlokConfigError::ValidationError)?;
+        Ok(config)
+    }
+
+    /// Deep merges the override file into the config, see `deep_merge`
+    fn merge_override(&mut self, path: &Path) -> Result<(), anyhow::Error> {
+        let content = read_to_string(path)?;
+        let overlay: Value = ConfigFormat::detect(path, &content).parse(&content, path)?;
+
+        let mut value = serde_yaml::to_value(&*self)?;
+        deep_merge(&mut value, overlay);
+        // The merged value has no location, so errors only point to the override file
+        let mut config: AppConfig =
+            serde_yaml::from_value(value).map_err(|e| Diagnostic::from_yaml_error(&e, path))?;
+
+        // Sources are not serialized, items added by the override come from the override file
+        let source = path.canonicalize()?;
+        for process in config.processes.iter_mut() {
+            process.source = self
+                .processes
+                .iter()
+                .find(|x| x.id == process.id)
+                .map_or(source.clone(), |x| x.source.clone());
+        }
+        for flock in config.flocks.iter_mut() {
+            flock.source = self
+                .flocks
+                .iter()
+                .find(|x| x.display_name == flock.display_name)
+                .map_or(source.clone(), |x| x.source.clone());
+        }
+        *self = config;
+        Ok(())
+    }
+
+    /// Merges the files included by the config at `path`, and the files they include in turn.
+    /// Files in `loaded` are skipped, so that each file is only merged once.
+    fn merge_includes(
+        &mut self,
+        path: &Path,
+        loaded: &mut Vec<PathBuf>,
+    ) -> Result<(), anyhow::Error> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let dir_pattern = glob::Pattern::escape(&dir.to_string_lossy());
+        for include in std::mem::take(&mut self.include) {
+            let pattern = Path::new(&dir_pattern).join(&include);
+            let mut paths = glob::glob(&pattern.to_string_lossy())
+                .map_err(|e| {
+                    anyhow!(
+                        "Invalid include \"{}\" in \"{}\": {}",
+                        include,
+                        path.display(),
+                        e
+                    )
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            paths.sort();
+            // A glob might match no file, but a plain path must exist
+            if paths.is_empty() && glob::Pattern::escape(&include) == include {
+                return Err(anyhow!(
+                    "File \"{}\" included in \"{}\" does not exist",
+                    include,
+                    path.display()
+                ));
+            }
+
+            for included_path in paths {
+                let canonical_path = included_path.canonicalize()?;
+                if loaded.contains(&canonical_path) {
+                    continue;
+                }
+                loaded.push(canonical_path);
+
+                let mut included = read_config_file(&included_path)?;
+                if !included.env.is_empty() || !included.env_file.is_empty() {
+                    return Err(anyhow!(
+                        "\"{}\" sets env or env_file, which is only supported in the main config file",
+                        included_path.display()
+                    ));
+                }
+                included.merge_includes(&included_path, loaded)?;
+                self.merge(included)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the processes and flocks of `other`, which must not reuse ids or flock names
+    fn merge(&mut self, other: AppConfig) -> Result<(), anyhow::Error> {
+        for process in other.processes {
+            if let Some(existing) = self.processes.iter().find(|x| x.id == process.id) {
+                return Err(anyhow!(
+                    "Process \"{}\" is defined in both \"{}\" and \"{}\"",
+                    process.id,
+                    existing.source.display(),
+                    process.source.display()
+                ));
+            }
+            self.processes.push(process);
+        }
+        for flock in other.flocks {
+            if let Some(existing) = self
+                .flocks
+                .iter()
+                .find(|x| x.display_name == flock.display_name)
+            {
+                return Err(anyhow!(
+                    "Flock \"{}\" is defined in both \"{}\" and \"{}\"",
+                    flock.display_name,
+                    existing.source.display(),
+                    flock.source.display()
+                ));
+            }
+            self.flocks.push(flock);
+        }
+        Ok(())
+    }
+
+    /// Validates the config and the references between its processes and flocks, reporting
+    /// every problem at once. `config_file` is the main config file the config was loaded from.
+    pub fn check(&self, config_file: &Path) -> Result<(), Diagnostics> {
+        let mut problems = match self.validate() {
+            Ok(()) => vec![],
+            Err(errors) => Problem::from_errors(&errors),
+        };
+        problems.extend(self.validate_references());
+
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(Diagnostics::from_problems(problems, self, config_file)),
+        }
+    }
+
+    /// Checks that ids and flock names are unique, that every referenced process exists, that
+    /// processes do not depend on each other in a cycle and that every process can be started
+    fn validate_references(&self) -> Vec<Problem> {
+        let mut problems = vec![];
+        let ids: Vec<_> = self.processes.iter().map(|x| x.id.as_str()).collect();
+        let unknown_process = |path: Vec<Segment>, message: String, id: &str| {
+            Problem::new(path, message)
+                .help(suggest(id, ids.iter().copied()).map(|x| format!("did you mean `{}`?", x)))
+        };
+
+        for (i, process) in self.processes.iter().enumerate() {
+            if ids[..i].contains(&process.id.as_str()) {
+                problems.push(Problem::new(
+                    vec!["processes".into(), i.into(), "id".into()],
+                    format!(
+                        "Process id \"{}\" is used by more than one process",
+                        process.id
+                    ),
+                ));
+            }
+            for (j, dependency) in process.depends_on.iter().enumerate() {
+                if !ids.contains(&dependency.as_str()) {
+                    problems.push(unknown_process(
+                        vec!["processes".into(), i.into(), "depends_on".into(), j.into()],
+                        format!(
+                            "Process \"{}\" depends on \"{}\", which does not exist",
+                            process.id, dependency
+                        ),
+                        dependency,
+                    ));
+                }
+            }
+        }
+
+        for (i, flock) in self.flocks.iter().enumerate() {
+            if self.flocks[..i]
+                .iter()
+                .any(|x| x.display_name == flock.display_name)
+            {
+                problems.push(Problem::new(
+                    vec!["flocks".into(), i.into(), "display_name".into()],
+                    format!(
+                        "Flock name \"{}\" is used by more than one flock",
+                        flock.display_name
+                    ),
+                ));
+            }
+            for (j, id) in flock.processes.iter().enumerate() {
+                if !ids.contains(&id.as_str()) {
+                    problems.push(unknown_process(
+                        vec!["flocks".into(), i.into(), "processes".into(), j.into()],
+                        format!(
+                            "Flock \"{}\" refers to process \"{}\", which does not exist",
+                            flock.display_name, id
+                        ),
+                        id,
+                    ));
+                }
+            }
+        }
+
+        problems.extend(self.validate_cycles());
+
+        // Processes are only started by their flocks, directly or as a dependency
+        let mut reachable: Vec<&str> = vec![];
+        let mut pending: Vec<&str> = self
+            .flocks
+            .iter()
+            .flat_map(|x| x.processes.iter().map(String::as_str))
+            .collect();
+        while let Some(id) = pending.pop() {
+            if reachable.contains(&id) {
+                continue;
+            }
+            reachable.push(id);
+            if let Some(process) = self.processes.iter().find(|x| x.id == id) {
+                pending.extend(process.depends_on.iter().map(String::as_str));
+            }
+        }
+        for (i, process) in self.processes.iter().enumerate() {
+            if !reachable.contains(&process.id.as_str()) {
+                problems.push(Problem::new(
+                    vec!["processes".into(), i.into()],
+                    format!(
+                        "Process \"{}\" is not part of any flock nor a dependency of one, so it \
+                         can never be started",
+                        process.id
+                    ),
+                ));
+            }
+        }
+
+        problems
+    }
+
+    /// Reports each cycle of dependencies once, at the dependency closing the cycle
+    fn validate_cycles(&self) -> Vec<Problem> {
+        fn visit(
+            config: &AppConfig,
+            i: usize,
+            path: &mut Vec<usize>,
+            visited: &mut Vec<usize>,
+            problems: &mut Vec<Problem>,
+        ) {
+            let process = &config.processes[i];
+            path.push(i);
+            for (j, dependency) in process.depends_on.iter().enumerate() {
+                let Some(dependency) = config.processes.iter().position(|x| &x.id == dependency)
+                else {
+                    continue;
+                };
+                if let Some(start) = path.iter().position(|x| *x == dependency) {
+                    let cycle: Vec<_> = path[start..]
+                        .iter()
+                        .chain([&dependency])
+                        .map(|x| config.processes[*x].id.as_str())
+                        .collect();
+                    problems.push(Problem::new(
+                        vec!["processes".into(), i.into(), "depends_on".into(), j.into()],
+                        format!(
+                            "Processes depend on each other in a cycle: {}",
+                            cycle.join(" -> ")
+                        ),
+                    ));
+                } else if !visited.contains(&dependency) {
+                    visit(config, dependency, path, visited, problems);
+                }
+            }
+            path.pop();
+            visited.push(i);
+        }
+
+        let mut problems = vec![];
+        let mut visited = vec![];
+        for i in 0..self.processes.len() {
+            if !visited.contains(&i) {
+                visit(self, i, &mut vec![], &mut visited, &mut problems);
+            }
+        }
+        problems
+    }
+
+    /// Resolves the config as loaded from `config_file` into what the processes run with: env
+    /// files are read, the environment of each process is merged from every level, variables
+    /// are interpolated and relative paths are resolved against the directory of `config_file`,
+    /// the project root, so that they do not depend on where flok is run from.
+    pub fn resolve(&mut self, config_file: &Path) -> Result<(), FlokConfigError> {
+        // The parent of a file name alone is empty
+        let config_dir = config_file
+            .parent()
+            .filter(|x| !x.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let project_root = std::path::absolute(config_dir)?;
+        // Paths of included files are relative to the file defining them
+        let source_dir = |source: &Path| source.parent().unwrap_or(&project_root).to_path_buf();
+        let resolve_all = |paths: &mut Vec<PathBuf>, dir: &Path| {
+            paths.iter_mut().for_each(|x| *x = dir.join(&x));
+        };
+        resolve_all(&mut self.env_file, &project_root);
+        for flock in self.flocks.iter_mut() {
+            resolve_all(&mut flock.env_file, &source_dir(&flock.source));
+        }
+        for process in self.processes.iter_mut() {
+            resolve_all(&mut process.env_file, &source_dir(&process.source));
+        }
+
+        // Env files are watched too, so that editing them reloads the config. Their paths are
+        // canonical like the ones of the loaded files, for the watcher to match changed paths
+        let env_files: Vec<_> = self
+            .env_file
+            .iter()
+            .chain(self.flocks.iter().flat_map(|x| x.env_file.iter()))
+            .chain(self.processes.iter().flat_map(|x| x.env_file.iter()))
+            .map(|x| x.canonicalize().unwrap_or(x.clone()))
+            .collect();
+        for file in env_files {
+            if !self.watched_files.contains(&file) {
+                self.watched_files.push(file);
+            }
+        }
+
+        self.project_root = project_root.clone();
+        self.resolve_env()?;
+        if let Err(problems) = self.interpolate() {
+            return Err(FlokConfigError::ValidationError(
+                Diagnostics::from_problems(problems, self, config_file),
+            ));
+        }
+
+        for process in self.processes.iter_mut() {
+            process.cwd = Some(match &process.cwd {
+                Some(cwd) => source_dir(&process.source).join(cwd),
+                None => self.project_root.clone(),
+            });
+            if let WatchConfig::WithOptions(options) = &mut process.watch {
+                resolve_all(&mut options.paths, &source_dir(&process.source));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the env files and merges the environment of the config, its flocks and the process
+    /// into the `env` of each process, so that it holds every variable to set for the process
+    fn resolve_env(&mut self) -> Result<(), anyhow::Error> {
+        let global_env = read_env(&self.env, &self.env_file)?;
+        let flock_envs = self
+            .flocks
+            .iter()
+            .map(|x| Ok((x, read_env(&x.env, &x.env_file)?)))
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+        for process in self.processes.iter_mut() {
+            // A process can be part of multiple flocks, which must then agree on its environment
+            let mut flock_env: BTreeMap<&String, (&Option<String>, &str)> = BTreeMap::new();
+            for (flock, env) in flock_envs.iter() {
+                if !flock.processes.contains(&process.id) {
+                    continue;
+                }
+                for (key, value) in env.iter() {
+                    match flock_env.get(key) {
+                        Some((other_value, other_flock)) if *other_value != value => {
+                            return Err(anyhow!(
+                                "Flocks \"{}\" and \"{}\" disagree on \"{}\" of process \"{}\"",
+                                other_flock,
+                                flock.display_name,
+                                key,
+                                process.id
+                            ));
+                        }
+                        _ => {
+                            flock_env.insert(key, (value, &flock.display_name));
+                        }
+                    }
+                }
+            }
+
+            let mut env = global_env.clone();
+            env.extend(
+                flock_env
+                    .into_iter()
+                    .map(|(key, (value, _))| (key.to_owned(), value.to_owned())),
+            );
+            env.extend(read_env(&process.env, &process.env_file)?);
+            process.env = env;
+        }
+        Ok(())
+    }
+
+    /// Interpolates `${VAR}`, `${VAR:-default}` and `${process.<id>.env.<VAR>}` in the command,
+    /// cwd and env of every process. `${VAR}` refers to the environment of the process, and to the
+    /// environment of flok when the process does not set it. Variables that are not set are left
+    /// to the shell in the command, e.g. loop variables, and are problems in the cwd and env.
+    fn interpolate(&mut self) -> Result<(), Vec<Problem>> {
+        let mut resolver = EnvResolver::new(&self.processes);
+        let mut interpolated = vec![];
+        let mut problems: Vec<Problem> = vec![];
+        let mut errors: Vec<String> = vec![];
+        for (i, process) in self.processes.iter().enumerate() {
+            // A variable referred to by other variables fails for each of them, only the first
+            // one is reported
+            let mut report = |path: Vec<Segment>, field: &str, e: anyhow::Error| {
+                let error = e.to_string();
+                if !errors.contains(&error) {
+                    let mut path_to_field = vec!["processes".into(), i.into()];
+                    path_to_field.extend(path);
+                    problems.push(Problem::new(
+                        path_to_field,
+                        format!(
+                            "Unable to interpolate {} of process \"{}\": {}",
+                            field, process.id, error
+                        ),
+                    ));
+                    errors.push(error);
+                }
+            };
+
+            let mut env = EnvConfig::new();
+            for key in process.env.keys() {
+                match resolver.resolve(i, key) {
+                    Ok(value) => {
+                        env.insert(key.to_owned(), value);
+                    }
+                    Err(e) => report(
+                        vec!["env".into(), key.as_str().into()],
+                        &format!("env \"{}\"", key),
+                        e,
+                    ),
+                }
+            }
+            let command = interpolate(&process.command, true, |name| resolver.lookup(i, name))
+                .unwrap_or_else(|e| {
+                    report(vec!["command".into()], "command", e);
+                    process.command.to_owned()
+                });
+            let cwd = process.cwd.as_ref().map(|x| {
+                interpolate(&x.to_string_lossy(), false, |name| resolver.lookup(i, name))
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|e| {
+                        report(vec!["cwd".into()], "cwd", e);
+                        x.to_owned()
+                    })
+            });
+            interpolated.push((env, command, cwd));
+        }
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        for (process, (env, command, cwd)) in self.processes.iter_mut().zip(interpolated) {
+            process.env = env;
+            process.command = command;
+            process.cwd = cwd;
+        }
+        Ok(())
+    }
+}
+
+/// Resolves the interpolated value of the environment variables of processes, following the
+/// references between them
+struct EnvResolver<'a> {
+    processes: &'a [ProcessConfig],
+    /// Values of the resolved variables, or why they could not be resolved, so that a variable
+    /// failing for every variable referring to it fails the same way for each of them
+    resolved: BTreeMap<(usize, String), Result<Option<String>, String>>,
+    /// Variables being resolved, to detect references in a cycle
+    stack: Vec<(usize, String)>,
+}
+
+impl<'a> EnvResolver<'a> {
+    fn new(processes: &'a [ProcessConfig]) -> Self {
+        Self {
+            processes,
+            resolved: BTreeMap::new(),
+            stack: vec![],
+        }
+    }
+
+    /// Value of the variable `name` of the process at `index`, as referred to in an interpolation
+    fn lookup(&mut self, index: usize, name: &str) -> Result<Option<String>, anyhow::Error> {
+        let Some(reference) = name.strip_prefix("process.") else {
+            return self.resolve(index, name);
+        };
+        let (id, key) = reference
+            .split_once(".env.")
+            .ok_or_else(|| anyhow!("\"{}\" must be in the form process.<id>.env.<VAR>", name))?;
+        let index = self
+            .processes
+            .iter()
+            .position(|x| x.id == id)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Process \"{}\" referred to by \"{}\" does not exist",
+                    id,
+                    name
+                )
+            })?;
+        self.resolve(index, key)
+    }
+
+    fn resolve(&mut self, index: usize, key: &str) -> Result<Option<String>, anyhow::Error> {
+        let variable = (index, key.to_owned());
+        if let Some(value) = self.resolved.get(&variable) {
+            return value.clone().map_err(|e| anyhow!(e));
+        }
+
+        let value = match self.processes[index].env.get(key) {
+            // A variable referring to itself extends the inherited value, e.g. PATH: ${PATH}:bin
+            _ if self.stack.last() == Some(&variable) => std::env::var(key).ok(),
+            _ if self.stack.contains(&variable) => {
+                let cycle = self
+                    .stack
+                    .iter()
+                    .skip_while(|x| **x != variable)
+                    .chain([&variable])
+                    .map(|(i, key)| format!("{}.{}", self.processes[*i].id, key))
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                return Err(anyhow!(
+                    "Variables refer to each other in a cycle: {}",
+                    cycle
+                ));
+            }
+            Some(Some(template)) => {
+                self.stack.push(variable.clone());
+                let value = interpolate(template, false, |name| self.lookup(index, name));
+                self.stack.pop();
+                match value {
+                    Ok(value) => Some(value),
+                    Err(e) => {
+                        self.resolved.insert(variable, Err(e.to_string()));
+                        return Err(e);
+                    }
+                }
+            }
+            Some(None) => None,
+            None => std::env::var(key).ok(),
+        };
+        self.resolved.insert(variable, Ok(value.clone()));
+        Ok(value)
+    }
+}
+
+/// Reads a single config file in any of the supported formats, keeping track of the file in its processes and flocks
+fn read_config_file(path: &Path) -> Result<AppConfig, anyhow::Error> {
+    let content = read_to_string(path)?;
+    let mut config: AppConfig = ConfigFormat::detect(path, &content).parse(&content, path)?;
+
+    let source = path.canonicalize()?;
+    for process in config.processes.iter_mut() {
+        process.source = source.clone();
+    }
+    for flock in config.flocks.iter_mut() {
+        flock.source = source.clone();
+    }
+    Ok(config)
+}
+
+fn read_to_string(path: &Path) -> Result<String, anyhow::Error> {
+    fs::read_to_string(path).map_err(|_| {
+        anyhow!(
+            "Unable to open \"{}\", please check if it exists and is readable",
+            path.display()
+        )
+    })
+}
+
+/// Variables of the env files in order, overridden by the variables of `env`
+fn read_env(env: &EnvConfig, env_file: &[PathBuf]) -> Result<EnvConfig, anyhow::Error> {
+    let mut result = EnvConfig::new();
+    // Missing env files are reported by the validation instead
+    for path in env_file.iter().filter(|x| x.is_file()) {
+        let error = |e| anyhow!("Unable to read env file \"{}\": {}", path.display(), e);
+        for item in dotenvy::from_path_iter(path).map_err(error)? {
+            let (key, value) = item.map_err(error)?;
+            result.insert(key, Some(value));
+        }
+    }
+    result.extend(env.clone());
+    Ok(result)
+}
+
+fn validate_command(command: &str) -> Result<(), Error> {
+    match command.trim().is_empty() {
+        true => Err(Error::Custom("The command must not be empty".to_string())),
+        false => Ok(()),
+    }
+}
+
+fn validate_cwd(cwd: Option<&Path>) -> Result<(), Error> {
+    match cwd {
+        Some(cwd) if !cwd.is_dir() => Err(Error::Custom(format!(
+            "Working directory \"{}\" does not exist",
+            cwd.display()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn validate_watch(watch: &WatchConfig) -> Result<(), Vec<Error>> {
+    let WatchConfig::WithOptions(options) = watch else {
+        return Ok(());
+    };
+    let missing_paths = options
+        .paths
+        .iter()
+        .filter(|x| !x.exists())
+        .map(|x| Error::Custom(format!("Watched path \"{}\" does not exist", x.display())));
+    let globs = options.include.iter().chain(&options.exclude);
+    let invalid_globs = globs.filter_map(|x| {
+        glob::Pattern::new(x)
+            .err()
+            .map(|e| Error::Custom(format!("Invalid glob \"{x}\": {e}")))
+    });
+    let errors: Vec<_> = missing_paths.chain(invalid_globs).collect();
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+fn validate_env_files(env_file: &[PathBuf]) -> Result<(), Error> {
+    match env_file.iter().find(|x| !x.is_file()) {
+        Some(path) => Err(Error::Custom(format!(
+            "Env file \"{}\" does not exist",
+            path.display()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Value of an environment variable as written in the config
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum EnvValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+/// Accepts numbers and booleans as values too, as YAML does not require quoting them
+fn deserialize_env<'de, D>(deserializer: D) -> Result<EnvConfig, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let env = BTreeMap::<String, Option<EnvValue>>::deserialize(deserializer)?;
+    Ok(env
+        .into_iter()
+        .map(|(key, value)| {
+            let value = value.map(|x| match x {
+                EnvValue::String(x) => x,
+                EnvValue::Integer(x) => x.to_string(),
+                EnvValue::Float(x) => x.to_string(),
+                EnvValue::Boolean(x) => x.to_string(),
+            });
+            (key, value)
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Validate, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct FlockConfig {
     pub display_name: String,
+    #[validate(min_items = 1)]
     pub processes: Vec<String>,
+    /// Environment of the processes of the flock, overridden by the environment of the processes
+    #[serde(default, deserialize_with = "deserialize_env")]
+    #[schemars(with = "BTreeMap<String, Option<EnvValue>>")]
+    pub env: EnvConfig,
+    /// Dotenv files relative to the config file, overridden by `env`
+    #[serde(default)]
+    #[validate(custom = validate_env_files)]
+    pub env_file: Vec<PathBuf>,
+    /// Config file defining the flock
+    #[serde(skip)]
+    pub source: PathBuf,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Validate, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ProcessConfig {
     pub id: String,
     pub display_name: String,
+    #[validate(custom = validate_command)]
     pub command: String,
+    /// Working directory of the command, relative to the config file. The directory of the config
+    /// file is used when not set.
+    #[validate(custom = |x: &Option<PathBuf>| validate_cwd(x.as_deref()))]
+    pub cwd: Option<PathBuf>,
     #[serde(default)]
+    #[validate(custom = validate_watch)]
     pub watch: WatchConfig,
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    /// Maximum number of automatic restarts, unlimited when not set
+    pub max_restarts: Option<u32>,
+    #[serde(default)]
+    #[validate]
+    pub backoff: BackoffConfig,
+    /// Ids of the processes that must be ready before this process is started
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Probes deciding when the process is ready, it is ready as soon as it is spawned otherwise
+    #[validate]
+    pub ready_when: Option<ReadyWhenConfig>,
+    /// Probes checked periodically once the process is ready, to detect hanging processes
+    #[validate]
+    pub healthcheck: Option<HealthcheckConfig>,
+    /// Environment of the process, on top of the one inherited from flok
+    #[serde(default, deserialize_with = "deserialize_env")]
+    #[schemars(with = "BTreeMap<String, Option<EnvValue>>")]
+    pub env: EnvConfig,
+    /// Dotenv files relative to the config file, overridden by `env`
+    #[serde(default)]
+    #[validate(custom = validate_env_files)]
+    pub env_file: Vec<PathBuf>,
+    /// Config file defining the process
+    #[serde(skip)]
+    pub source: PathBuf,
+}
+
+/// Every configured probe must pass for the process to be considered ready
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Validate, JsonSchema)]
+#[validate(custom = |s| s.validate_probes())]
+#[serde(deny_unknown_fields)]
+pub struct ReadyWhenConfig {
+    /// Port on localhost that accepts TCP connections
+    pub tcp_port: Option<u16>,
+    /// URL that responds to a GET request with a 2xx status
+    #[validate(pattern = "^http://")]
+    pub http: Option<String>,
+    /// Regex matched against the output of the process
+    pub log: Option<String>,
+    /// Shell command that exits with 0
+    pub command: Option<String>,
+    #[serde(default = "ReadyWhenConfig::default_interval_seconds")]
+    #[validate(exclusive_minimum = 0.0)]
+    pub interval_seconds: f64,
+    #[serde(default = "ReadyWhenConfig::default_timeout_seconds")]
+    #[validate(exclusive_minimum = 0.0)]
+    pub timeout_seconds: f64,
+}
+
+impl ReadyWhenConfig {
+    fn default_interval_seconds() -> f64 {
+        1.0
+    }
+
+    fn default_timeout_seconds() -> f64 {
+        5.0
+    }
+
+    fn validate_probes(&self) -> Result<(), Error> {
+        if self.tcp_port.is_none()
+            && self.http.is_none()
+            && self.log.is_none()
+            && self.command.is_none()
+        {
+            return Err(Error::Custom(
+                "ready_when requires at least one of tcp_port, http, log or command".to_string(),
+            ));
+        }
+        if let Some(log) = &self.log {
+            Regex::new(log).map_err(|e| Error::Custom(format!("Invalid log regex: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f64(self.interval_seconds)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.timeout_seconds)
+    }
+}
+
+/// Every configured probe must pass for the process to be considered healthy
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Validate, JsonSchema)]
+#[validate(custom = |s| s.validate_probes())]
+#[serde(deny_unknown_fields)]
+pub struct HealthcheckConfig {
+    /// Port on localhost that accepts TCP connections
+    pub tcp_port: Option<u16>,
+    /// URL that responds to a GET request with a 2xx status
+    #[validate(pattern = "^http://")]
+    pub http: Option<String>,
+    /// Shell command that exits with 0
+    pub command: Option<String>,
+    #[serde(default = "HealthcheckConfig::default_interval_seconds")]
+    #[validate(exclusive_minimum = 0.0)]
+    pub interval_seconds: f64,
+    #[serde(default = "HealthcheckConfig::default_timeout_seconds")]
+    #[validate(exclusive_minimum = 0.0)]
+    pub timeout_seconds: f64,
+    /// Consecutive failures before the process is considered unhealthy
+    #[serde(default = "HealthcheckConfig::default_retries")]
+    #[validate(minimum = 1)]
+    pub retries: u32,
+    /// Whether to restart the process once it is unhealthy
+    #[serde(default)]
+    pub restart: bool,
+}
+
+impl HealthcheckConfig {
+    fn default_interval_seconds() -> f64 {
+        10.0
+    }
+
+    fn default_timeout_seconds() -> f64 {
+        5.0
+    }
+
+    fn default_retries() -> u32 {
+        3
+    }
+
+    fn validate_probes(&self) -> Result<(), Error> {
+        if self.tcp_port.is_none() && self.http.is_none() && self.command.is_none() {
+            return Err(Error::Custom(
+                "healthcheck requires at least one of tcp_port, http or command".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f64(self.interval_seconds)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.timeout_seconds)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    OnFailure,
+    Always,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+impl RestartPolicy {
+    pub fn should_restart(&self, success: bool) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => !success,
+            RestartPolicy::Always => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Validate, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BackoffConfig {
+    #[serde(default = "BackoffConfig::default_initial_seconds")]
+    #[validate(minimum = 0.0)]
+    pub initial_seconds: f64,
+    #[serde(default = "BackoffConfig::default_max_seconds")]
+    #[validate(minimum = 0.0)]
+    pub max_seconds: f64,
+    #[serde(default = "BackoffConfig::default_multiplier")]
+    #[validate(minimum = 1.0)]
+    pub multiplier: f64,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        BackoffConfig {
+            initial_seconds: Self::default_initial_seconds(),
+            max_seconds: Self::default_max_seconds(),
+            multiplier: Self::default_multiplier(),
+        }
+    }
+}
+
+impl BackoffConfig {
+    fn default_initial_seconds() -> f64 {
+        1.0
+    }
+
+    fn default_max_seconds() -> f64 {
+        30.0
+    }
+
+    fn default_multiplier() -> f64 {
+        2.0
+    }
+
+    /// Delay before the next automatic restart, given the number of restarts done so far
+    pub fn delay(&self, restarts: u32) -> Duration {
+        let seconds = self.initial_seconds * self.multiplier.powi(restarts as i32);
+        Duration::from_secs_f64(seconds.min(self.max_seconds))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
 #[serde(untagged)]
 pub enum WatchConfig {
     Enabled(bool),
-    WithDebounce { debounce_seconds: Option<f64> },
+    WithOptions(WatchOptions),
+}
+
+/// Deserialized by hand rather than as an untagged enum, so that the errors of the object form,
+/// e.g. unknown keys, are reported instead of not matching any variant
+impl<'de> Deserialize<'de> for WatchConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct WatchConfigVisitor;
+
+        impl<'de> Visitor<'de> for WatchConfigVisitor {
+            type Value = WatchConfig;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a boolean or an object with the watch options")
+            }
+
+            fn visit_bool<E: de::Error>(self, value: bool) -> Result<Self::Value, E> {
+                Ok(WatchConfig::Enabled(value))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                WatchOptions::deserialize(de::value::MapAccessDeserializer::new(map))
+                    .map(WatchConfig::WithOptions)
+            }
+        }
+
+        deserializer.deserialize_any(WatchConfigVisitor)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WatchOptions {
+    pub debounce_seconds: Option<f64>,
+    /// Files and directories watched recursively, relative to the config file. The project root
+    /// is watched when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<PathBuf>,
+    /// Globs of the files that restart the process, every file when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Globs of the files that never restart the process, even when included
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Whether files ignored by git, and the `.git` directory, are skipped
+    #[serde(default = "WatchOptions::default_gitignore")]
+    pub gitignore: bool,
+}
+
+impl WatchOptions {
+    fn default_gitignore() -> bool {
+        true
+    }
 }
 
 impl Default for WatchConfig {
@@ -43,7 +1078,7 @@ impl WatchConfig {
     pub fn is_enabled(&self) -> bool {
         match self {
             WatchConfig::Enabled(enabled) => *enabled,
-            WatchConfig::WithDebounce { .. } => true,
+            WatchConfig::WithOptions(_) => true,
         }
     }
 
@@ -51,9 +1086,134 @@ impl WatchConfig {
         match self {
             WatchConfig::Enabled(true) => Duration::from_secs(2),
             WatchConfig::Enabled(false) => Duration::from_secs(0),
-            WatchConfig::WithDebounce { debounce_seconds } => {
-                Duration::from_secs_f64(debounce_seconds.unwrap_or(1.0))
+            WatchConfig::WithOptions(options) => {
+                Duration::from_secs_f64(options.debounce_seconds.unwrap_or(1.0))
+            }
+        }
+    }
+
+    /// Filter of the changed files that restart the process, watching `project_root` unless
+    /// other paths are given
+    pub fn filter(&self, project_root: &Path) -> WatchFilter {
+        match self {
+            WatchConfig::Enabled(_) => {
+                WatchFilter::new(vec![project_root.to_path_buf()], &[], &[], true)
+            }
+            WatchConfig::WithOptions(options) => {
+                let roots = match options.paths.is_empty() {
+                    true => vec![project_root.to_path_buf()],
+                    false => options.paths.clone(),
+                };
+                WatchFilter::new(roots, &options.include, &options.exclude, options.gitignore)
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(processes: &str) -> AppConfig {
+        serde_yaml::from_str(&format!("flocks: []\nprocesses:\n{processes}")).unwrap()
+    }
+
+    #[test]
+    fn interpolates_references_to_other_processes() {
+        let mut config = config(
+            "
+  - id: db
+    display_name: DB
+    command: postgres -p ${PORT}
+    env: { PORT: 5432 }
+  - id: api
+    display_name: API
+    command: serve --db localhost:${process.db.env.PORT}
+",
+        );
+        config.interpolate().unwrap();
+        assert_eq!(config.processes[0].command, "postgres -p 5432");
+        assert_eq!(config.processes[1].command, "serve --db localhost:5432");
+    }
+
+    #[test]
+    fn self_reference_extends_the_inherited_value() {
+        let mut config = config(
+            "
+  - id: api
+    display_name: API
+    command: serve
+    env: { PATH: \"${PATH}:x\" }
+",
+        );
+        config.interpolate().unwrap();
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(config.processes[0].env["PATH"], Some(format!("{path}:x")));
+    }
+
+    #[test]
+    fn fails_on_cycles() {
+        let mut config = config(
+            "
+  - id: api
+    display_name: API
+    command: serve
+    env: { A: \"${B}\", B: \"${process.db.env.C}\" }
+  - id: db
+    display_name: DB
+    command: postgres
+    env: { C: \"${process.api.env.A}\" }
+",
+        );
+        let problems = config.interpolate().unwrap_err();
+        // Reported once, for the first variable of the cycle
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        let problem = format!("{:?}", problems[0]);
+        assert!(
+            problem.contains(
+                "Variables refer to each other in a cycle: api.A -> api.B -> db.C -> api.A"
+            ),
+            "{problem}"
+        );
+    }
+
+    #[test]
+    fn leaves_unset_variables_of_the_command_to_the_shell() {
+        let mut config = config(
+            "
+  - id: api
+    display_name: API
+    command: for f in a b; do echo ${f}; done
+",
+        );
+        config.interpolate().unwrap();
+        assert_eq!(
+            config.processes[0].command,
+            "for f in a b; do echo ${f}; done"
+        );
+    }
+
+    #[test]
+    fn reports_unset_variables_of_the_env_and_cwd_with_their_path() {
+        let mut config = config(
+            "
+  - id: api
+    display_name: API
+    command: serve
+    cwd: ${FLOK_TEST_UNSET_DIR}
+    env: { URL: \"${FLOK_TEST_UNSET_HOST}\" }
+",
+        );
+        let problems = format!("{:?}", config.interpolate().unwrap_err());
+        assert!(
+            problems.contains(
+                r#"path: [Key("processes"), Index(0), Key("env"), Key("URL")], message: "Unable to interpolate env \"URL\" of process \"api\": \"FLOK_TEST_UNSET_HOST\" is not set"#
+            ),
+            "{problems}"
+        );
+        assert!(
+            problems.contains(r#"path: [Key("processes"), Index(0), Key("cwd")]"#),
+            "{problems}"
+        );
+    }
+}
@@ -0,0 +1,664 @@
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_valid::validation::Errors;
+use toml::de::{DeTable, DeValue};
+use yaml_rust2::{
+    Event,
+    parser::{MarkedEventReceiver, Parser},
+    scanner::Marker,
+};
+
+use crate::config::AppConfig;
+use crate::utils::format::ConfigFormat;
+
+/// A config error, pointing to where it happened in the config files when known
+#[derive(Debug)]
+pub struct Diagnostic {
+    message: String,
+    file: Option<PathBuf>,
+    location: Option<Location>,
+    help: Option<String>,
+}
+
+/// Line and column of a config file, both starting at 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// Location of the byte at `offset` of `content`
+    fn from_offset(content: &str, offset: usize) -> Self {
+        let before = &content[..offset.min(content.len())];
+        let line_start = before.rfind('\n').map_or(0, |x| x + 1);
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl Diagnostic {
+    pub fn new(message: String) -> Self {
+        Self {
+            message,
+            file: None,
+            location: None,
+            help: None,
+        }
+    }
+
+    pub fn file(mut self, file: &Path) -> Self {
+        self.file = Some(file.to_path_buf());
+        self
+    }
+
+    pub fn location(mut self, location: Option<Location>) -> Self {
+        self.location = location;
+        self
+    }
+
+    pub fn help(mut self, help: Option<String>) -> Self {
+        self.help = help;
+        self
+    }
+
+    /// Turns a serde_yaml error into a diagnostic, suggesting the closest name for unknown
+    /// fields and variants
+    pub fn from_yaml_error(error: &serde_yaml::Error, file: &Path) -> Self {
+        let location = error.location().map(|x| Location {
+            line: x.line(),
+            column: x.column(),
+        });
+        Self::from_parse_error(error.to_string(), location, file)
+    }
+
+    pub fn from_json_error(error: &serde_json::Error, file: &Path) -> Self {
+        // serde_json reports errors that are not about the syntax at line 0
+        let location = Some(Location {
+            line: error.line(),
+            column: error.column(),
+        })
+        .filter(|x| x.line > 0);
+        Self::from_parse_error(error.to_string(), location, file)
+    }
+
+    /// `content` is the parsed file, as toml only gives the range of the error
+    pub fn from_toml_error(error: &toml::de::Error, content: &str, file: &Path) -> Self {
+        let location = error
+            .span()
+            .map(|x| Location::from_offset(content, x.start));
+        Self::from_parse_error(error.message().trim().to_string(), location, file)
+    }
+
+    fn from_parse_error(message: String, location: Option<Location>, file: &Path) -> Self {
+        // The location is rendered separately
+        let message = match message.rfind(" at line ") {
+            Some(i) if location.is_some() => message[..i].to_string(),
+            _ => message,
+        };
+        let suggestion = suggest_from_message(&message);
+        // The expected names are long to read through when the closest one is suggested
+        let message = match (&suggestion, message.find(", expected one of")) {
+            (Some(_), Some(i)) => message[..i].to_string(),
+            _ => message,
+        };
+        let help = suggestion.map(|x| format!("did you mean `{}`?", x));
+
+        Self::new(message).file(file).location(location).help(help)
+    }
+
+    /// The line of the file the diagnostic points to, read when rendering
+    fn snippet(&self) -> Option<String> {
+        let content = fs::read_to_string(self.file.as_ref()?).ok()?;
+        let line = self.location?.line;
+        content.lines().nth(line.checked_sub(1)?).map(str::to_owned)
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error: {}", self.message)?;
+        let gutter = " ".repeat(self.location.map_or(1, |x| x.line.to_string().len()));
+        match (&self.file, self.location) {
+            (Some(file), Some(location)) => {
+                write!(
+                    f,
+                    "\n{}--> {}:{}:{}",
+                    gutter,
+                    file.display(),
+                    location.line,
+                    location.column
+                )?;
+                if let Some(snippet) = self.snippet() {
+                    // Underline the word under the caret, e.g. the whole name of an unknown field
+                    let start = location.column.saturating_sub(1);
+                    let word = snippet
+                        .chars()
+                        .skip(start)
+                        .take_while(|x| !x.is_whitespace() && !matches!(x, ',' | ']' | '}'))
+                        .collect::<String>();
+                    let length = word.trim_end_matches(':').chars().count().max(1);
+                    write!(f, "\n{} |", gutter)?;
+                    write!(f, "\n{} | {}", location.line, snippet)?;
+                    write!(
+                        f,
+                        "\n{} | {}{}",
+                        gutter,
+                        " ".repeat(start),
+                        "^".repeat(length)
+                    )?;
+                }
+            }
+            (Some(file), None) => write!(f, "\n{}--> {}", gutter, file.display())?,
+            (None, _) => {}
+        }
+        if let Some(help) = &self.help {
+            write!(f, "\n{} = help: {}", gutter, help)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Every problem found in the config, so that they can be fixed at once
+#[derive(Debug)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in self.0.iter() {
+            writeln!(f, "{}\n", diagnostic)?;
+        }
+        match self.0.len() {
+            1 => write!(f, "Found 1 problem in the config"),
+            n => write!(f, "Found {} problems in the config", n),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+impl From<Diagnostic> for Diagnostics {
+    fn from(diagnostic: Diagnostic) -> Self {
+        Self(vec![diagnostic])
+    }
+}
+
+impl Diagnostics {
+    /// Locates the problems of the config in the files defining the invalid values.
+    /// `config_file` is the main config file, the processes and flocks keep track of the file
+    /// they come from.
+    pub fn from_problems(problems: Vec<Problem>, config: &AppConfig, config_file: &Path) -> Self {
+        Self(
+            problems
+                .into_iter()
+                .map(|problem| {
+                    let (file, path) = locate_item(&problem.path, config, config_file);
+                    let location = fs::read_to_string(file).ok().and_then(|content| {
+                        locate(&content, ConfigFormat::detect(file, &content), &path)
+                    });
+                    Diagnostic::new(problem.message)
+                        .file(file)
+                        .location(location)
+                        .help(problem.help)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// An invalid value of the config, before it is located in the config files
+#[derive(Debug)]
+pub struct Problem {
+    path: Vec<Segment>,
+    message: String,
+    help: Option<String>,
+}
+
+impl Problem {
+    pub fn new(path: Vec<Segment>, message: String) -> Self {
+        Self {
+            path,
+            message,
+            help: None,
+        }
+    }
+
+    pub fn help(mut self, help: Option<String>) -> Self {
+        self.help = help;
+        self
+    }
+
+    /// Flattens the validation errors into a problem per error
+    pub fn from_errors(errors: &Errors) -> Vec<Self> {
+        let mut problems = vec![];
+        collect_errors(errors, &mut vec![], &mut problems);
+        problems
+    }
+}
+
+/// Segment of the path to a value of the config
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+    /// Item of a list whose `key` is `value`, e.g. the process whose id is "api". `occurrence`
+    /// tells apart items sharing the same value.
+    Item {
+        key: String,
+        value: String,
+        occurrence: usize,
+    },
+}
+
+impl From<&str> for Segment {
+    fn from(key: &str) -> Self {
+        Segment::Key(key.to_owned())
+    }
+}
+
+impl From<usize> for Segment {
+    fn from(index: usize) -> Self {
+        Segment::Index(index)
+    }
+}
+
+/// The file defining the value at `path`, and the path of the value in that file. Processes and
+/// flocks are looked up by their id, as the files they come from are merged together.
+fn locate_item<'a>(
+    path: &[Segment],
+    config: &'a AppConfig,
+    config_file: &'a Path,
+) -> (&'a Path, Vec<Segment>) {
+    let [Segment::Key(list), Segment::Index(i), rest @ ..] = path else {
+        return (config_file, path.to_vec());
+    };
+    let items: Vec<_> = match list.as_str() {
+        "processes" => config
+            .processes
+            .iter()
+            .map(|x| ("id", x.id.as_str(), x.source.as_path()))
+            .collect(),
+        "flocks" => config
+            .flocks
+            .iter()
+            .map(|x| ("display_name", x.display_name.as_str(), x.source.as_path()))
+            .collect(),
+        _ => vec![],
+    };
+    let Some((key, value, source)) = items.get(*i).copied() else {
+        return (config_file, path.to_vec());
+    };
+    let occurrence = items[..*i]
+        .iter()
+        .filter(|(_, x, y)| *x == value && *y == source)
+        .count();
+
+    let mut item_path = vec![
+        Segment::Key(list.to_owned()),
+        Segment::Item {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            occurrence,
+        },
+    ];
+    item_path.extend_from_slice(rest);
+    (source, item_path)
+}
+
+fn collect_errors(errors: &Errors, path: &mut Vec<Segment>, problems: &mut Vec<Problem>) {
+    let messages = match errors {
+        Errors::Array(errors) => {
+            for (i, item) in errors.items.iter() {
+                path.push(Segment::Index(*i));
+                collect_errors(item, path, problems);
+                path.pop();
+            }
+            &errors.errors
+        }
+        Errors::Object(errors) => {
+            for (property, item) in errors.properties.iter() {
+                // serde_valid names fields with a default function after the function, e.g.
+                // "BackoffConfig::default_multiplier" for "multiplier"
+                let property = property
+                    .rsplit_once("::default_")
+                    .map_or(property.as_str(), |(_, x)| x);
+                path.push(Segment::Key(property.to_owned()));
+                collect_errors(item, path, problems);
+                path.pop();
+            }
+            &errors.errors
+        }
+        Errors::NewType(errors) => errors,
+    };
+    problems.extend(
+        messages
+            .iter()
+            .map(|x| Problem::new(path.clone(), x.to_string())),
+    );
+}
+
+/// Finds where the value at `path` is defined in `content`, falling back to the closest parent
+/// that is defined, e.g. for values left to their default. Errors of the whole file have no
+/// location.
+pub fn locate(content: &str, format: ConfigFormat, path: &[Segment]) -> Option<Location> {
+    let mut node = match format {
+        // JSON is read as YAML, which it is a subset of
+        ConfigFormat::Yaml | ConfigFormat::Json => {
+            let mut builder = NodeBuilder::default();
+            Parser::new_from_str(content)
+                .load(&mut builder, false)
+                .ok()?;
+            builder.root?
+        }
+        ConfigFormat::Toml => {
+            let table = DeTable::parse(content).ok()?;
+            Node::from_toml(
+                &toml::Spanned::new(table.span(), DeValue::Table(table.into_inner())),
+                content,
+            )
+        }
+    };
+
+    let mut location = None;
+    for segment in path {
+        let found = match (segment, &node.value) {
+            (Segment::Key(key), NodeValue::Mapping(entries)) => entries
+                .iter()
+                .find(|(k, _)| k.as_scalar() == Some(key))
+                // Scalars are pointed at directly, collections at their key
+                .map(|(k, v)| match v.value {
+                    NodeValue::Scalar(_) => (v.location, v.clone()),
+                    _ => (k.location, v.clone()),
+                }),
+            (Segment::Index(i), NodeValue::Sequence(items)) => {
+                items.get(*i).map(|x| (x.start(), x.clone()))
+            }
+            (
+                Segment::Item {
+                    key,
+                    value,
+                    occurrence,
+                },
+                NodeValue::Sequence(items),
+            ) => items
+                .iter()
+                .filter(|x| x.get(key).and_then(Node::as_scalar) == Some(value))
+                .nth(*occurrence)
+                .map(|x| (x.start(), x.clone())),
+            _ => None,
+        };
+        let Some((start, child)) = found else {
+            break;
+        };
+        location = Some(start);
+        node = child;
+    }
+    location
+}
+
+/// Minimal tree of the config file keeping where each node starts
+#[derive(Debug, Clone)]
+struct Node {
+    value: NodeValue,
+    location: Location,
+}
+
+#[derive(Debug, Clone)]
+enum NodeValue {
+    Scalar(String),
+    Sequence(Vec<Node>),
+    Mapping(Vec<(Node, Node)>),
+}
+
+impl Node {
+    fn from_toml(value: &toml::Spanned<DeValue>, content: &str) -> Self {
+        let location = Location::from_offset(content, value.span().start);
+        let value = match value.get_ref() {
+            DeValue::String(x) => NodeValue::Scalar(x.to_string()),
+            DeValue::Array(items) => {
+                NodeValue::Sequence(items.iter().map(|x| Node::from_toml(x, content)).collect())
+            }
+            DeValue::Table(entries) => NodeValue::Mapping(
+                entries
+                    .iter()
+                    .map(|(k, v)| {
+                        let key = Node {
+                            value: NodeValue::Scalar(k.get_ref().to_string()),
+                            location: Location::from_offset(content, k.span().start),
+                        };
+                        (key, Node::from_toml(v, content))
+                    })
+                    .collect(),
+            ),
+            // Only strings are compared when locating values
+            _ => NodeValue::Scalar(String::new()),
+        };
+        Self { value, location }
+    }
+
+    fn as_scalar(&self) -> Option<&String> {
+        match &self.value {
+            NodeValue::Scalar(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Where the node starts in the file. Mappings start at their first key, as YAML marks block
+    /// mappings after it, TOML at the header of the table and TOML keys are not kept in order.
+    fn start(&self) -> Location {
+        match &self.value {
+            NodeValue::Mapping(entries) => entries
+                .iter()
+                .map(|(k, _)| k.location)
+                .min_by_key(|x| (x.line, x.column))
+                .unwrap_or(self.location),
+            _ => self.location,
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Node> {
+        match &self.value {
+            NodeValue::Mapping(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_scalar().map(String::as_str) == Some(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct NodeBuilder {
+    /// Collections being built, with the key waiting for its value in mappings
+    stack: Vec<(Node, Option<Node>)>,
+    root: Option<Node>,
+}
+
+impl NodeBuilder {
+    fn push(&mut self, node: Node) {
+        let Some((parent, pending_key)) = self.stack.last_mut() else {
+            self.root = Some(node);
+            return;
+        };
+        match &mut parent.value {
+            NodeValue::Sequence(items) => items.push(node),
+            NodeValue::Mapping(entries) => match pending_key.take() {
+                Some(key) => entries.push((key, node)),
+                None => *pending_key = Some(node),
+            },
+            NodeValue::Scalar(_) => {}
+        }
+    }
+}
+
+impl MarkedEventReceiver for NodeBuilder {
+    fn on_event(&mut self, event: Event, marker: Marker) {
+        let location = Location {
+            line: marker.line(),
+            column: marker.col() + 1,
+        };
+        match event {
+            Event::Scalar(value, ..) => self.push(Node {
+                value: NodeValue::Scalar(value),
+                location,
+            }),
+            Event::SequenceStart(..) => self.stack.push((
+                Node {
+                    value: NodeValue::Sequence(vec![]),
+                    location,
+                },
+                None,
+            )),
+            Event::MappingStart(..) => self.stack.push((
+                Node {
+                    value: NodeValue::Mapping(vec![]),
+                    location,
+                },
+                None,
+            )),
+            Event::SequenceEnd | Event::MappingEnd => {
+                if let Some((node, _)) = self.stack.pop() {
+                    self.push(node);
+                }
+            }
+            // Aliases are not followed, their values are located at the alias instead
+            Event::Alias(_) => self.push(Node {
+                value: NodeValue::Scalar(String::new()),
+                location,
+            }),
+            _ => {}
+        }
+    }
+}
+
+/// Suggests the closest expected name for messages such as "unknown field `dispaly_name`,
+/// expected one of `id`, `display_name`, `command`"
+fn suggest_from_message(message: &str) -> Option<String> {
+    if !message.contains("unknown field") && !message.contains("unknown variant") {
+        return None;
+    }
+    let mut quoted = message.split('`').skip(1).step_by(2);
+    let unknown = quoted.next()?;
+    suggest(unknown, quoted)
+}
+
+/// The candidate closest to `name`, if any is close enough to be a typo
+pub fn suggest<'a, I>(name: &str, candidates: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    candidates
+        .into_iter()
+        .map(|x| (strsim::damerau_levenshtein(name, x), x))
+        .filter(|(distance, x)| *distance <= (x.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, x)| x.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YAML: &str = "\
+flocks: []
+processes:
+  - id: api
+    command: cargo run
+  - id: web
+    command: npm start
+    watch:
+      paths: [src]
+";
+
+    const TOML: &str = "\
+flocks = []
+
+[[processes]]
+id = \"api\"
+command = \"cargo run\"
+";
+
+    fn at(line: usize, column: usize) -> Option<Location> {
+        Some(Location { line, column })
+    }
+
+    #[test]
+    fn suggests_the_closest_candidate() {
+        let candidates = ["display_name", "command", "cwd"];
+        assert_eq!(
+            suggest("dispaly_name", candidates),
+            Some("display_name".to_owned())
+        );
+        assert_eq!(suggest("cmd", candidates), Some("cwd".to_owned()));
+        assert_eq!(suggest("restart", candidates), None);
+        assert_eq!(suggest("x", []), None);
+    }
+
+    #[test]
+    fn location_from_offset_counts_lines_and_characters() {
+        let content = "a: 1\nbé: 2\n";
+        assert_eq!(
+            Location::from_offset(content, 0),
+            Location { line: 1, column: 1 }
+        );
+        assert_eq!(
+            Location::from_offset(content, 5),
+            Location { line: 2, column: 1 }
+        );
+        assert_eq!(
+            Location::from_offset(content, 9),
+            Location { line: 2, column: 4 }
+        );
+        assert_eq!(
+            Location::from_offset(content, 100),
+            Location { line: 3, column: 1 }
+        );
+    }
+
+    #[test]
+    fn locates_values_in_yaml() {
+        let locate = |path: &[Segment]| locate(YAML, ConfigFormat::Yaml, path);
+        assert_eq!(locate(&[]), None);
+        assert_eq!(locate(&["processes".into()]), at(2, 1));
+        assert_eq!(locate(&["processes".into(), 1.into()]), at(5, 5));
+        assert_eq!(
+            locate(&["processes".into(), 1.into(), "command".into()]),
+            at(6, 14)
+        );
+        assert_eq!(
+            locate(&["processes".into(), 1.into(), "watch".into(), "paths".into()]),
+            at(8, 7)
+        );
+        let item = Segment::Item {
+            key: "id".to_owned(),
+            value: "web".to_owned(),
+            occurrence: 0,
+        };
+        assert_eq!(locate(&["processes".into(), item, "id".into()]), at(5, 9));
+    }
+
+    #[test]
+    fn falls_back_to_the_closest_defined_parent() {
+        let path = ["processes".into(), 0.into(), "cwd".into()];
+        assert_eq!(locate(YAML, ConfigFormat::Yaml, &path), at(3, 5));
+        let path = ["processes".into(), 5.into()];
+        assert_eq!(locate(YAML, ConfigFormat::Yaml, &path), at(2, 1));
+    }
+
+    #[test]
+    fn locates_values_in_toml_and_json() {
+        let path = ["processes".into(), 0.into(), "command".into()];
+        assert_eq!(locate(TOML, ConfigFormat::Toml, &path), at(5, 11));
+        let json =
+            "{\n  \"processes\": [\n    {\"id\": \"api\", \"command\": \"cargo run\"}\n  ]\n}";
+        assert_eq!(locate(json, ConfigFormat::Json, &path), at(3, 30));
+    }
+}
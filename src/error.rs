@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::diagnostics::Diagnostics;
+
 #[derive(Debug, Error)]
 pub enum FlokError {
     #[error("Error while processing config\n{0}")]
@@ -12,7 +14,7 @@ pub enum FlokError {
 #[derive(Debug, Error)]
 pub enum FlokConfigError {
     #[error("{0}")]
-    ValidationError(#[from] serde_valid::validation::Errors),
+    ValidationError(Diagnostics),
     #[error("{0}")]
     Known(#[from] anyhow::Error),
     #[error("An unknown IO error has occured: {0}")]
@@ -33,6 +35,8 @@ pub enum FlokProgramError {
 
 #[derive(Debug, Error)]
 pub enum FlokProgramInitError {
+    #[error("{0}")]
+    Known(#[from] anyhow::Error),
     #[error("An unknown IO error has occured: {0}")]
     UnknownStdIo(#[from] std::io::Error),
     #[error("Program crashed due to an unknown error")]
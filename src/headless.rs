@@ -0,0 +1,121 @@
+use std::{
+    io::{IsTerminal, Write},
+    thread,
+};
+
+use anyhow::anyhow;
+use crossterm::style::{Color, Stylize};
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM},
+    iterator::Signals,
+};
+
+use crate::{
+    config::AppConfig,
+    error::{FlokProgramError, FlokProgramExecutionError, FlokProgramInitError},
+    state::{GlobalUIState, stop_processes},
+};
+
+const PREFIX_COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Yellow,
+    Color::Green,
+    Color::Magenta,
+    Color::Blue,
+    Color::Red,
+];
+
+/// Launches the given flock and prints the output of its processes line by line, prefixed by
+/// the process name, until flok is interrupted.
+pub fn run(config: AppConfig, flock_name: &str) -> Result<(), FlokProgramError> {
+    let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP])
+        .map_err(|e| FlokProgramError::Init(FlokProgramInitError::UnknownStdIo(e)))?;
+
+    let global_state = GlobalUIState::new(config);
+    let available = global_state
+        .flocks
+        .iter()
+        .map(|f| format!("\"{}\"", f.display_name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let flock = global_state
+        .flocks
+        .iter()
+        .find(|f| f.display_name == flock_name)
+        .ok_or_else(|| {
+            FlokProgramError::Init(FlokProgramInitError::Known(anyhow!(
+                "Flock \"{}\" does not exist, available flocks are: {}",
+                flock_name,
+                available
+            )))
+        })?;
+
+    let colored = std::io::stdout().is_terminal();
+    let name_width = flock
+        .process_states
+        .iter()
+        .map(|x| x.process_config.display_name.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    for (i, process_state) in flock.process_states.iter().enumerate() {
+        let prefix = format!(
+            "{:width$} | ",
+            process_state.process_config.display_name,
+            width = name_width
+        );
+        let prefix = if colored {
+            prefix
+                .with(PREFIX_COLORS[i % PREFIX_COLORS.len()])
+                .to_string()
+        } else {
+            prefix
+        };
+
+        // Subscribe before launching so that no output is missed
+        let mut output = process_state.subscribe_output();
+        thread::spawn(move || {
+            let mut pending = vec![];
+            while let Ok(chunk) = output.recv() {
+                pending.extend_from_slice(&chunk);
+                while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = pending.drain(..=end).collect();
+                    print_line(&prefix, &line);
+                }
+            }
+        });
+    }
+
+    for process_state in flock.process_states.iter() {
+        if let Err(e) = process_state.launch() {
+            // The processes launched so far are not left running
+            stop_running_processes(&global_state);
+            return Err(FlokProgramError::Execution(
+                FlokProgramExecutionError::Known(e),
+            ));
+        }
+    }
+
+    signals.forever().next();
+    stop_running_processes(&global_state);
+
+    Ok(())
+}
+
+/// Stops the processes that are running, including the dependencies outside of the flock
+fn stop_running_processes(global_state: &GlobalUIState) {
+    let process_states = global_state.running_processes();
+    eprintln!("Stopping {} processes...", process_states.len());
+    stop_processes(&process_states);
+}
+
+fn print_line(prefix: &str, line: &[u8]) {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+    let mut stdout = std::io::stdout().lock();
+    let _ = stdout.write_all(prefix.as_bytes());
+    let _ = stdout.write_all(line);
+    let _ = stdout.write_all(b"\n");
+    let _ = stdout.flush();
+}
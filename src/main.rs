@@ -1,14 +1,16 @@
-use std::{fs::File, path::PathBuf};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
 
 use anyhow::anyhow;
 use clap::{Parser, Subcommand};
 use error::{FlokConfigError, FlokError};
-use serde_valid::Validate;
 
-use crate::config::AppConfig;
+use crate::config::{AppConfig, CONFIG_FILE_NAMES, find_config_file};
 
 mod config;
+mod diagnostics;
 mod error;
+mod headless;
 mod state;
 mod ui;
 mod utils;
@@ -19,56 +21,128 @@ struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 
-    #[arg(short, long, default_value=None)]
+    /// Path of the config file, looked up in the current directory and its parents up to the git
+    /// root when not given
+    #[arg(short, long, global = true, default_value=None)]
     config_file: Option<PathBuf>,
 }
 
 #[derive(Debug, Subcommand)]
 enum Commands {
     Version,
+    /// Start a flock without the TUI, streaming the output of its processes to stdout
+    Up {
+        /// Display name of the flock to start
+        flock: String,
+    },
+    /// Check the config and print every problem found, exiting with a non-zero code when it is
+    /// invalid, e.g. in a pre-commit hook
+    Validate,
+    /// Print the JSON Schema of the config, e.g. for editors to complete and check flok.yaml
+    Schema,
+    /// Inspect the config
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommands {
+    /// Print the effective config, after merging includes and the override file and resolving
+    /// env and variables
+    Show {
+        /// Print the config as JSON instead of YAML
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 fn main() {
     match process_cmd() {
         Ok(_) => {}
         Err(e) => {
-            println!("{}", e.to_string());
+            eprintln!("{}", e);
+            std::process::exit(1);
         }
     }
 }
 
-fn process_config(cli: Cli) -> Result<AppConfig, FlokConfigError> {
-    let config_file = cli.config_file.unwrap_or("./flok.yaml".into());
-
-    let config: AppConfig =
-        serde_yaml::from_reader(File::open(config_file.clone()).map_err(move |_| {
-            // TODO more fine grain error handling
-            anyhow!(format!(
-                "Unable to open \"{}\", please check if it exists and is readable",
-                config_file.to_string_lossy().to_string()
-            ))
-        })?)?;
-
-    config.validate()?;
+/// The config file given on the command line, or the one found from the current directory
+fn find_config(config_file: Option<PathBuf>) -> Result<PathBuf, FlokConfigError> {
+    match config_file {
+        Some(config_file) => Ok(config_file),
+        None => {
+            let cwd = std::env::current_dir()?;
+            Ok(find_config_file(&cwd).ok_or_else(|| {
+                anyhow!(
+                    "Unable to find {} in \"{}\" or its parent directories",
+                    CONFIG_FILE_NAMES.join(", "),
+                    cwd.display()
+                )
+            })?)
+        }
+    }
+}
 
-    Ok(config)
+fn process_config(config_file: Option<PathBuf>) -> Result<AppConfig, FlokConfigError> {
+    AppConfig::from_file(&find_config(config_file)?)
 }
 
 fn process_cmd() -> Result<(), FlokError> {
     let args = Cli::try_parse();
     match args {
-        Ok(args) if args.command.is_some() => show_version(),
-        Ok(args) => {
-            ui::run(process_config(args)?)?;
-        }
-        Err(msg) => {
-            let _ = msg.print();
-        }
+        Ok(args) => match args.command {
+            Some(Commands::Version) => show_version(),
+            Some(Commands::Up { flock }) => {
+                headless::run(process_config(args.config_file)?, &flock)?;
+            }
+            Some(Commands::Validate) => {
+                process_config(args.config_file)?;
+                println!("The config is valid");
+            }
+            Some(Commands::Schema) => {
+                let schema = schemars::schema_for!(AppConfig);
+                let schema = serde_json::to_string_pretty(&schema)
+                    .map_err(|e| FlokConfigError::from(anyhow::Error::from(e)))?;
+                print_output(&format!("{}\n", schema))?;
+            }
+            Some(Commands::Config {
+                command: ConfigCommands::Show { json },
+            }) => {
+                let config = process_config(args.config_file)?;
+                let output = if json {
+                    let json = serde_json::to_string_pretty(&config)
+                        .map_err(|e| FlokConfigError::from(anyhow::Error::from(e)))?;
+                    format!("{}\n", json)
+                } else {
+                    serde_yaml::to_string(&config).map_err(FlokConfigError::from)?
+                };
+                print_output(&output)?;
+            }
+            None => {
+                let config_file = find_config(args.config_file)?;
+                ui::run(AppConfig::from_file(&config_file)?, config_file)?;
+            }
+        },
+        Err(msg) => msg.exit(),
     }
 
     Ok(())
 }
 
+/// Prints `output` to stdout, where a closed pipe is not an error, e.g. when piped to `head`
+fn print_output(output: &str) -> Result<(), FlokConfigError> {
+    let mut stdout = std::io::stdout().lock();
+    match stdout
+        .write_all(output.as_bytes())
+        .and_then(|_| stdout.flush())
+    {
+        Err(e) if e.kind() != ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
 fn show_version() {
     println!("Flok version v{}", env!("CARGO_PKG_VERSION"));
 }
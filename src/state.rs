@@ -1,18 +1,33 @@
 use std::{
-    sync::{Arc, RwLock},
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
     thread,
+    time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use bus::{Bus, BusReader};
 
 use crate::{
     config::{AppConfig, FlockConfig, ProcessConfig},
     utils::{
-        file_watcher::{FILE_WATCHER, FileWatcherStatus, WatcherEvent, ensure_watcher_initialized},
-        process::{Process, ProcessRunningStatus, ProcessStatus, RestartDebounceHandler},
+        file_watcher::{
+            FILE_WATCHER, FileChanges, FileWatcherStatus, WatcherEvent, ensure_watching,
+        },
+        probe::{HealthCheck, ReadinessProbe},
+        process::{
+            Process, ProcessOutput, ProcessRunningStatus, ProcessStatus, RestartDebounceHandler,
+        },
     },
 };
 
+/// How often a waiting process checks whether its dependencies are ready
+const DEPENDENCY_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
 // pub struct AppState {
 //     pub active_ui: ActiveUIState,
 //     flock_processes: Arc<Vec<FlockState>>,
@@ -24,55 +39,345 @@ pub enum AppState {
 
 pub struct GlobalUIState {
     pub flocks: Vec<FlockState>,
+    /// Every configured process, including the ones only started as a dependency
+    pub process_states: Vec<Arc<ProcessState>>,
 }
 
-impl AppState {
+impl GlobalUIState {
     pub fn new(config: AppConfig) -> Self {
-        let process_states = config
-            .processes
-            .into_iter()
-            .map(|x| Arc::new(ProcessState::new(x)))
+        let process_states =
+            ProcessState::from_configs(config.processes, &config.project_root, &[]);
+        Self {
+            flocks: config
+                .flocks
+                .into_iter()
+                .map(|flock_cfg| FlockState::from_config(flock_cfg, &process_states))
+                .collect(),
+            process_states,
+        }
+    }
+
+    /// Replaces the config while the processes are running. Processes whose config is unchanged
+    /// keep running, changed processes are restarted with their new config if they were active,
+    /// removed processes are stopped, and processes added to an active flock are launched.
+    pub fn apply_config(&mut self, config: AppConfig) -> ConfigChanges {
+        let previous = std::mem::take(&mut self.process_states);
+        let process_states =
+            ProcessState::from_configs(config.processes, &config.project_root, &previous);
+
+        let mut changes = ConfigChanges::default();
+        let mut relaunched = vec![];
+        for state in previous.iter() {
+            let id = &state.process_config.id;
+            match process_states.iter().find(|x| &x.process_config.id == id) {
+                Some(new_state) if Arc::ptr_eq(&new_state.status, &state.status) => {
+                    // Waiting processes wait for the previous state of changed dependencies
+                    if state.is_waiting()
+                        && new_state
+                            .dependencies
+                            .iter()
+                            .any(|x| !previous.iter().any(|y| Arc::ptr_eq(&x.status, &y.status)))
+                    {
+                        relaunched.push((state.clone(), new_state.clone()));
+                    }
+                }
+                Some(new_state) => {
+                    changes.changed.push(id.to_owned());
+                    if state.is_active() {
+                        relaunched.push((state.clone(), new_state.clone()));
+                    } else {
+                        // Stopping an exited process ends its file watching along with what is
+                        // left of its group
+                        let state = state.clone();
+                        thread::spawn(move || state.stop());
+                    }
+                }
+                None => {
+                    changes.removed.push(id.to_owned());
+                    let state = state.clone();
+                    thread::spawn(move || state.stop());
+                }
+            }
+        }
+        changes.added = process_states
+            .iter()
+            .map(|x| x.process_config.id.to_owned())
+            .filter(|id| !previous.iter().any(|x| &x.process_config.id == id))
             .collect();
-        let flock_states = config
-            .flocks
+        for (state, new_state) in relaunched {
+            thread::spawn(move || {
+                state.stop();
+                let _ = new_state.launch();
+            });
+        }
+
+        let previous_flocks = std::mem::take(&mut self.flocks);
+        for flock_config in config.flocks {
+            let mut flock = FlockState::from_config(flock_config, &process_states);
+            match previous_flocks
+                .iter()
+                .find(|x| x.display_name == flock.display_name)
+            {
+                Some(previous) if previous.is_active => {
+                    flock.is_active = true;
+                    // Launch the processes added to the flock, changed processes are relaunched
+                    // above
+                    for state in flock.process_states.iter() {
+                        if !previous
+                            .process_states
+                            .iter()
+                            .any(|x| x.process_config.id == state.process_config.id)
+                        {
+                            let _ = state.launch();
+                        }
+                    }
+                }
+                Some(_) => {}
+                None => changes.added_flocks.push(flock.display_name.to_owned()),
+            }
+            self.flocks.push(flock);
+        }
+        changes.removed_flocks = previous_flocks
             .into_iter()
-            .map(|flock_cfg| FlockState::from_config(flock_cfg, &process_states))
+            .map(|x| x.display_name)
+            .filter(|x| !self.flocks.iter().any(|y| &y.display_name == x))
             .collect();
 
+        self.process_states = process_states;
+        changes
+    }
+
+    /// Processes that are currently running or waiting to be started, along with the exited
+    /// ones whose group still has processes or that are about to be restarted, so that stopping
+    /// them leaves nothing orphaned
+    pub fn running_processes(&self) -> Vec<Arc<ProcessState>> {
+        self.process_states
+            .iter()
+            .filter(|x| x.is_active() || x.has_leftover_processes() || x.is_restart_pending())
+            .cloned()
+            .collect()
+    }
+}
+
+/// Summary of what changed when the config was reloaded, by process id and flock name
+#[derive(Debug, Default)]
+pub struct ConfigChanges {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+    pub added_flocks: Vec<String>,
+    pub removed_flocks: Vec<String>,
+}
+
+impl ConfigChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.changed.is_empty()
+            && self.removed.is_empty()
+            && self.added_flocks.is_empty()
+            && self.removed_flocks.is_empty()
+    }
+}
+
+impl Display for ConfigChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes to the processes");
+        }
+        let parts = [
+            ("processes added", &self.added),
+            ("processes restarted", &self.changed),
+            ("processes removed", &self.removed),
+            ("flocks added", &self.added_flocks),
+            ("flocks removed", &self.removed_flocks),
+        ];
+        let summary: Vec<_> = parts
+            .iter()
+            .filter(|(_, x)| !x.is_empty())
+            .map(|(label, x)| format!("{}: {}", label, x.join(", ")))
+            .collect();
+        write!(f, "{}", summary.join("; "))
+    }
+}
+
+/// Gracefully stops all the given processes in parallel, blocking until all of them have exited
+pub fn stop_processes(process_states: &[Arc<ProcessState>]) {
+    thread::scope(|s| {
+        process_states.iter().for_each(|x| {
+            s.spawn(|| x.stop());
+        });
+    });
+}
+
+impl AppState {
+    pub fn new(config: AppConfig) -> Self {
         Self::Main(
-            MainUIState { active_flock: 0 },
-            GlobalUIState {
-                flocks: flock_states,
+            MainUIState {
+                active_flock: 0,
+                active_process: 0,
+                focus: MainUIFocus::Flocks,
+                show_details: false,
             },
+            GlobalUIState::new(config),
         )
     }
 
-    pub fn next_item(&mut self) {
+    pub fn running_processes(&self) -> Vec<Arc<ProcessState>> {
+        match self {
+            AppState::Main(_, global_state) => global_state.running_processes(),
+        }
+    }
+
+    /// Applies a reloaded config, keeping the selected flock when it still exists
+    pub fn apply_config(&mut self, config: AppConfig) -> ConfigChanges {
         match self {
             AppState::Main(state, global_state) => {
-                state.next_flock(global_state.flocks.len());
+                let active_flock = global_state
+                    .flocks
+                    .get(state.active_flock)
+                    .map(|x| x.display_name.to_owned());
+                let changes = global_state.apply_config(config);
+
+                let position = global_state
+                    .flocks
+                    .iter()
+                    .position(|x| Some(&x.display_name) == active_flock.as_ref());
+                if position != Some(state.active_flock) {
+                    state.active_flock = position.unwrap_or(0);
+                    state.active_process = 0;
+                }
+                let no_of_process = global_state.flocks[state.active_flock].process_states.len();
+                if state.active_process >= no_of_process {
+                    state.active_process = 0;
+                }
+                changes
             }
         }
     }
+
+    pub fn next_item(&mut self) {
+        match self {
+            AppState::Main(state, global_state) => match state.focus {
+                MainUIFocus::Flocks => state.next_flock(global_state.flocks.len()),
+                MainUIFocus::Processes => state.next_process(&global_state.flocks),
+            },
+        }
+    }
     pub fn previous_item(&mut self) {
         match self {
-            AppState::Main(state, global_state) => {
-                state.previous_flock(global_state.flocks.len());
-            }
+            AppState::Main(state, global_state) => match state.focus {
+                MainUIFocus::Flocks => state.previous_flock(global_state.flocks.len()),
+                MainUIFocus::Processes => state.previous_process(&global_state.flocks),
+            },
         }
     }
 
-    pub fn select(&mut self) {
+    pub fn select(&mut self) -> Result<()> {
         match self {
-            AppState::Main(state, global_state) => {
-                state.launch_flock(&global_state.flocks);
+            AppState::Main(state, global_state) => match state.focus {
+                MainUIFocus::Flocks => state.launch_flock(&mut global_state.flocks)?,
+                MainUIFocus::Processes => {
+                    if let Some(process_state) = state.selected_process(&global_state.flocks) {
+                        process_state.launch()?;
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+
+    pub fn switch_focus(&mut self) {
+        match self {
+            AppState::Main(state, _) => {
+                state.focus = match state.focus {
+                    MainUIFocus::Flocks => MainUIFocus::Processes,
+                    MainUIFocus::Processes => MainUIFocus::Flocks,
+                };
+            }
+        }
+    }
+
+    pub fn start_process(&mut self) -> Result<()> {
+        if let Some(process_state) = self.focused_process() {
+            process_state.launch()?;
+        }
+        Ok(())
+    }
+
+    pub fn restart_process(&mut self) -> Result<()> {
+        if let Some(process_state) = self.focused_process() {
+            if process_state.is_running() {
+                process_state.restart();
+            } else {
+                process_state.launch()?;
             }
         }
+        Ok(())
+    }
+
+    /// Stops the selected flock or process, depending on which list has the focus
+    pub fn stop(&mut self) {
+        match self {
+            AppState::Main(state, global_state) => match state.focus {
+                MainUIFocus::Flocks => state.stop_flock(&mut global_state.flocks),
+                MainUIFocus::Processes => {
+                    if let Some(process_state) = state.selected_process(&global_state.flocks) {
+                        thread::spawn(move || process_state.stop());
+                    }
+                }
+            },
+        }
+    }
+
+    pub fn kill_process(&mut self) {
+        if let Some(process_state) = self.focused_process() {
+            thread::spawn(move || process_state.kill());
+        }
+    }
+
+    pub fn toggle_details(&mut self) {
+        match self {
+            AppState::Main(state, _) => state.show_details = !state.show_details,
+        }
+    }
+
+    pub fn close_details(&mut self) {
+        match self {
+            AppState::Main(state, _) => state.show_details = false,
+        }
     }
+
+    /// The process whose details are shown, if any
+    pub fn detailed_process(&self) -> Option<Arc<ProcessState>> {
+        match self {
+            AppState::Main(state, _) if state.show_details => self.focused_process(),
+            _ => None,
+        }
+    }
+
+    /// The selected process, only when the process list has the focus
+    fn focused_process(&self) -> Option<Arc<ProcessState>> {
+        match self {
+            AppState::Main(state, global_state) => match state.focus {
+                MainUIFocus::Flocks => None,
+                MainUIFocus::Processes => state.selected_process(&global_state.flocks),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MainUIFocus {
+    Flocks,
+    Processes,
 }
 
 pub struct MainUIState {
     pub active_flock: usize,
+    pub active_process: usize,
+    pub focus: MainUIFocus,
+    /// Whether the details of the focused process are shown over the panes
+    pub show_details: bool,
 }
 
 impl MainUIState {
@@ -82,6 +387,7 @@ impl MainUIState {
             next_flock_wrapped = 0
         }
         self.active_flock = next_flock_wrapped;
+        self.active_process = 0;
     }
     fn previous_flock(&mut self, no_of_flock: usize) {
         if self.active_flock == 0 {
@@ -89,59 +395,227 @@ impl MainUIState {
         } else {
             self.active_flock -= 1;
         };
+        self.active_process = 0;
+    }
+    fn next_process(&mut self, flocks: &[FlockState]) {
+        let no_of_process = flocks[self.active_flock].process_states.len();
+        if no_of_process > 0 {
+            self.active_process = (self.active_process + 1) % no_of_process;
+        }
+    }
+    fn previous_process(&mut self, flocks: &[FlockState]) {
+        let no_of_process = flocks[self.active_flock].process_states.len();
+        if no_of_process > 0 {
+            self.active_process = (self.active_process + no_of_process - 1) % no_of_process;
+        }
     }
-    fn launch_flock(&mut self, flocks: &[FlockState]) {
+    fn selected_process(&self, flocks: &[FlockState]) -> Option<Arc<ProcessState>> {
         flocks
             .get(self.active_flock)
+            .and_then(|x| x.process_states.get(self.active_process))
+            .cloned()
+    }
+    fn launch_flock(&mut self, flocks: &mut [FlockState]) -> Result<()> {
+        let flock = flocks
+            .get_mut(self.active_flock)
+            .expect("Flock should exists, but didn't");
+        flock.is_active = true;
+
+        // A process that cannot be spawned does not prevent the rest of the flock from launching
+        let mut errors: Vec<String> = vec![];
+        for process_state in flock.process_states.iter() {
+            if let Err(e) = process_state.launch() {
+                // A dependency shared by several processes fails for each of them
+                let error = e.to_string();
+                if !errors.contains(&error) {
+                    errors.push(error);
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(errors.join("\n")))
+        }
+    }
+    /// Stops the processes of the flock and their dependencies in the background, except the
+    /// ones that another active flock needs
+    fn stop_flock(&mut self, flocks: &mut [FlockState]) {
+        flocks
+            .get_mut(self.active_flock)
             .expect("Flock should exists, but didn't")
+            .is_active = false;
+
+        let in_use: Vec<_> = flocks
+            .iter()
+            .filter(|x| x.is_active)
+            .flat_map(|x| x.process_states.iter().cloned())
+            .collect();
+        flocks[self.active_flock]
             .process_states
             .iter()
+            .filter(|x| !in_use.iter().any(|y| Arc::ptr_eq(x, y)))
             .for_each(|x| {
-                x.launch().unwrap();
+                let process_state = x.clone();
+                thread::spawn(move || process_state.stop());
             });
     }
 }
 
 pub struct FlockState {
     pub display_name: String,
+    /// Processes of the flock, followed by the processes they depend on outside of the flock,
+    /// so that every process the flock starts has a pane
     pub process_states: Vec<Arc<ProcessState>>,
+    /// Whether the flock was launched and not stopped since
+    pub is_active: bool,
 }
 
 impl FlockState {
-    fn from_config(config: FlockConfig, process_states: &Vec<Arc<ProcessState>>) -> Self {
+    /// The config is expected to be validated, i.e. every process of the flock exists
+    fn from_config(config: FlockConfig, process_states: &[Arc<ProcessState>]) -> Self {
+        let members: Vec<_> = config
+            .processes
+            .iter()
+            .filter_map(|x| process_states.iter().find(|y| &y.process_config.id == x))
+            .cloned()
+            .collect();
+
+        // Dependencies, direct or not, in the order of the config
+        let mut dependencies: Vec<Arc<ProcessState>> = vec![];
+        let mut pending = members.clone();
+        while let Some(state) = pending.pop() {
+            for dependency in state.dependencies.iter() {
+                if !dependencies.iter().any(|x| Arc::ptr_eq(x, dependency)) {
+                    dependencies.push(dependency.clone());
+                    pending.push(dependency.clone());
+                }
+            }
+        }
+        let dependencies = process_states.iter().filter(|x| {
+            dependencies.iter().any(|y| Arc::ptr_eq(x, y))
+                && !members.iter().any(|y| Arc::ptr_eq(x, y))
+        });
+
         Self {
             display_name: config.display_name,
-            process_states: config
-                .processes
-                .iter()
-                .filter_map(|x| process_states.iter().find(|y| &y.process_config.id == x))
-                .cloned()
-                .collect(),
+            process_states: members.iter().chain(dependencies).cloned().collect(),
+            is_active: false,
         }
     }
 }
 
+#[derive(Clone)]
 pub struct ProcessState {
     pub process_config: Arc<ProcessConfig>,
     pub status: Arc<RwLock<ProcessStatus>>,
+    pub output: ProcessOutput,
+    /// Number of automatic restarts done by the restart policy since the last manual launch
+    pub restarts: Arc<AtomicU32>,
+    /// Processes that must be ready before this one is started
+    pub dependencies: Vec<Arc<ProcessState>>,
+    /// Changes to the watched files, with paths relative to the project root when inside it
+    pub changes: Arc<RwLock<FileChanges>>,
+    /// Whether a thread reacts to the file changes for the process, so that relaunching the
+    /// process does not start another one
+    is_watching: Arc<AtomicBool>,
+    /// Directory of the config file, which is watched for file changes
+    project_root: Arc<PathBuf>,
 }
 
 impl ProcessState {
-    pub fn new(process_config: ProcessConfig) -> Self {
+    pub fn new(
+        process_config: ProcessConfig,
+        dependencies: Vec<Arc<ProcessState>>,
+        project_root: Arc<PathBuf>,
+    ) -> Self {
         Self {
             process_config: Arc::new(process_config),
             status: Arc::new(RwLock::new(ProcessStatus::Stopped)),
+            output: Arc::new(Mutex::new(Bus::new(100))),
+            restarts: Arc::new(AtomicU32::new(0)),
+            dependencies,
+            changes: Arc::new(RwLock::new(FileChanges::default())),
+            is_watching: Arc::new(AtomicBool::new(false)),
+            project_root,
+        }
+    }
+
+    /// Creates the states in dependency order, so that each state can refer to its dependencies.
+    /// The configs are expected to be validated, i.e. free of cycles. States in `previous` whose
+    /// config is unchanged are carried over, along with their running process.
+    pub fn from_configs(
+        configs: Vec<ProcessConfig>,
+        project_root: &Path,
+        previous: &[Arc<Self>],
+    ) -> Vec<Arc<Self>> {
+        let project_root = Arc::new(project_root.to_path_buf());
+        let ids: Vec<_> = configs.iter().map(|x| x.id.to_owned()).collect();
+        let mut pending = configs;
+        let mut process_states: Vec<Arc<Self>> = vec![];
+        while !pending.is_empty() {
+            let is_created =
+                |id: &String| process_states.iter().any(|x| &x.process_config.id == id);
+            let position = pending
+                .iter()
+                .position(|x| {
+                    x.depends_on
+                        .iter()
+                        .all(|id| is_created(id) || !ids.contains(id))
+                })
+                // Only reachable with a cycle, in which case the remaining dependencies are ignored
+                .unwrap_or(0);
+            let config = pending.remove(position);
+            let dependencies = config
+                .depends_on
+                .iter()
+                .filter_map(|id| process_states.iter().find(|x| &x.process_config.id == id))
+                .cloned()
+                .collect();
+            let mut state = Self::new(config, dependencies, project_root.clone());
+            if let Some(previous) = previous
+                .iter()
+                .find(|x| x.process_config == state.process_config)
+            {
+                state.status = previous.status.clone();
+                state.output = previous.output.clone();
+                state.restarts = previous.restarts.clone();
+                state.changes = previous.changes.clone();
+                state.is_watching = previous.is_watching.clone();
+            }
+            process_states.push(Arc::new(state));
         }
+
+        // Keep the order of the config
+        ids.iter()
+            .filter_map(|id| process_states.iter().find(|x| &x.process_config.id == id))
+            .cloned()
+            .collect()
+    }
+
+    /// Subscribes to the raw output of the process, across restarts. The reader must be
+    /// drained continuously as the process blocks on output while the buffer is full.
+    pub fn subscribe_output(&self) -> BusReader<Vec<u8>> {
+        self.output.lock().unwrap().add_rx()
     }
 
+    /// Launches the process, along with its dependencies. The process waits for its
+    /// dependencies to be ready before it is started.
     pub fn launch(&self) -> Result<()> {
         fn is_launchable(status: &ProcessStatus) -> bool {
-            status == &ProcessStatus::Stopped
+            matches!(
+                status,
+                ProcessStatus::Stopped | ProcessStatus::Exited { .. }
+            )
+        }
+
+        for dependency in self.dependencies.iter() {
+            dependency.launch()?;
         }
 
         let can_launch = {
             if let Ok(status) = self.status.read() {
-                is_launchable(&*status)
+                is_launchable(&status)
             } else {
                 false
             }
@@ -149,25 +623,351 @@ impl ProcessState {
 
         if can_launch {
             if let Ok(mut status) = self.status.write() {
-                if is_launchable(&*status) {
+                if is_launchable(&status) {
                     // Initialize watcher lazily if this is a watchable process
                     if self.process_config.watch.is_enabled() {
                         self.enable_file_watching()
                     }
 
-                    *status = ProcessStatus::Running(Process::new(
-                        self.process_config.command.to_owned(),
-                    )?);
+                    self.restarts.store(0, Ordering::SeqCst);
+                    if self.dependencies.is_empty() {
+                        let process = self
+                            .spawn()
+                            .map_err(|e| anyhow!("{}: {}", self.process_config.display_name, e))?;
+                        *status = ProcessStatus::Running(process);
+                    } else {
+                        *status = ProcessStatus::Waiting;
+                        self.spawn_dependency_waiter();
+                    }
                 }
             }
         }
 
         Ok(())
     }
-    fn enable_file_watching(&self) {
-        ensure_watcher_initialized();
+
+    /// Starts the process once all its dependencies are ready, unless it is stopped meanwhile
+    fn spawn_dependency_waiter(&self) {
+        let state = self.clone();
+        thread::spawn(move || {
+            loop {
+                thread::sleep(DEPENDENCY_POLL_INTERVAL);
+                if !state.pending_dependencies().is_empty() {
+                    if state.is_waiting() {
+                        continue;
+                    }
+                    return;
+                }
+
+                if let Ok(mut status) = state.status.write() {
+                    if let ProcessStatus::Waiting = &*status {
+                        *status = match state.spawn() {
+                            Ok(process) => ProcessStatus::Running(process),
+                            Err(_) => ProcessStatus::Stopped,
+                        };
+                    }
+                }
+                return;
+            }
+        });
+    }
+
+    /// Dependencies that are not ready yet
+    pub fn pending_dependencies(&self) -> Vec<Arc<ProcessState>> {
+        self.dependencies
+            .iter()
+            .filter(|x| !x.is_ready())
+            .cloned()
+            .collect()
+    }
+
+    /// Whether the process passed its readiness probes, so that processes depending on it can be
+    /// started
+    pub fn is_ready(&self) -> bool {
+        matches!(
+            self.status.read().as_deref(),
+            Ok(ProcessStatus::Running(Process {
+                status: ProcessRunningStatus::Ready,
+                ..
+            }))
+        )
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        matches!(self.status.read().as_deref(), Ok(ProcessStatus::Waiting))
+    }
+
+    /// Whether the process is running or waiting to be started
+    pub fn is_active(&self) -> bool {
+        self.is_running() || self.is_waiting()
+    }
+
+    /// Whether the process exited while processes it started are still alive in its group
+    pub fn has_leftover_processes(&self) -> bool {
+        match self.status.read().as_deref() {
+            Ok(ProcessStatus::Exited { process, .. }) => process.is_group_alive(),
+            _ => false,
+        }
+    }
+
+    /// Whether the process exited and its restart policy is going to start it again, which
+    /// stopping the process cancels
+    pub fn is_restart_pending(&self) -> bool {
+        let success = match self.status.read().as_deref() {
+            Ok(ProcessStatus::Exited { code, signal, .. }) => *code == Some(0) && signal.is_none(),
+            _ => return false,
+        };
+        let restarts = self.restarts.load(Ordering::SeqCst);
+        self.process_config.restart.should_restart(success)
+            && self
+                .process_config
+                .max_restarts
+                .is_none_or(|max_restarts| restarts < max_restarts)
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(self.status.read().as_deref(), Ok(ProcessStatus::Running(_)))
+    }
+
+    /// Gracefully stops the process if it is running, blocking until it has exited
+    pub fn stop(&self) {
+        self.stop_with(Process::terminate);
+    }
+
+    /// Stops the process right away with SIGKILL, for processes that do not respond to SIGTERM
+    pub fn kill(&self) {
+        self.stop_with(Process::kill);
+    }
+
+    fn stop_with(&self, terminate: fn(&Process)) {
+        let process = match self.status.write() {
+            Ok(mut status) => match &mut *status {
+                ProcessStatus::Running(process) => {
+                    process.status = ProcessRunningStatus::Stopping;
+                    Some(process.clone())
+                }
+                // Processes started by an exited shell might still be alive in its group
+                ProcessStatus::Exited { process, .. } => {
+                    let process = process.clone();
+                    *status = ProcessStatus::Stopped;
+                    Some(process)
+                }
+                ProcessStatus::Waiting => {
+                    *status = ProcessStatus::Stopped;
+                    None
+                }
+                ProcessStatus::Stopped => None,
+            },
+            Err(_) => None,
+        };
+
+        if let Some(process) = process {
+            terminate(&process);
+
+            if let Ok(mut status) = self.status.write() {
+                // The process might have been launched again while shutting down
+                if let ProcessStatus::Running(Process {
+                    status: ProcessRunningStatus::Stopping,
+                    ..
+                }) = &*status
+                {
+                    *status = ProcessStatus::Stopped;
+                }
+            }
+        }
+    }
+
+    /// Gracefully stops the running process and launches it again in the background
+    pub fn restart(&self) {
+        let process = match self.status.write() {
+            Ok(mut status) => match &mut *status {
+                ProcessStatus::Running(process) if !process.status.is_terminating() => {
+                    process.status = ProcessRunningStatus::Restarting;
+                    Some(process.clone())
+                }
+                _ => None,
+            },
+            Err(_) => None,
+        };
+
+        if let Some(process) = process {
+            let state = self.clone();
+            thread::spawn(move || {
+                process.terminate();
+
+                if let Ok(mut status) = state.status.write() {
+                    // The process might have been stopped while shutting down
+                    if let ProcessStatus::Running(Process {
+                        status: ProcessRunningStatus::Restarting,
+                        ..
+                    }) = &*status
+                    {
+                        // The command might not be runnable anymore, e.g. its cwd was removed
+                        *status = match state.spawn() {
+                            Ok(process) => ProcessStatus::Running(process),
+                            Err(_) => ProcessStatus::Stopped,
+                        };
+                        if let Ok(mut changes) = state.changes.write() {
+                            changes.pending.clear();
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    fn spawn(&self) -> Result<Process> {
+        // Subscribe before spawning, so that the log probe does not miss any output
+        let probe = self
+            .process_config
+            .ready_when
+            .clone()
+            .map(|config| ReadinessProbe::new(config, self.subscribe_output()));
+
+        let mut process = Process::new(
+            self.process_config.command.to_owned(),
+            self.process_config.cwd.as_deref(),
+            &self.process_config.env,
+            self.output.clone(),
+        )?;
+        let state = self.clone();
+        process.spawn_reaper(self.status.clone(), move || state.handle_exit());
+
+        match probe {
+            Some(probe) => self.spawn_readiness_checker(&process, probe),
+            None => process.status = ProcessRunningStatus::Ready,
+        }
+        if let Some(config) = self.process_config.healthcheck.clone() {
+            self.spawn_health_checker(&process, HealthCheck::new(config));
+        }
+        Ok(process)
+    }
+
+    /// Marks the process as ready once its probes pass, giving up once the process is replaced
+    fn spawn_readiness_checker(&self, process: &Process, probe: ReadinessProbe) {
         let status = self.status.clone();
-        let process_config = self.process_config.clone();
+        let child = process.child.clone();
+        let is_current = move |status: &ProcessStatus| match status {
+            ProcessStatus::Running(process) if Arc::ptr_eq(&process.child, &child) => {
+                Some(process.status == ProcessRunningStatus::Stable)
+            }
+            _ => None,
+        };
+
+        thread::spawn(move || {
+            loop {
+                thread::sleep(probe.interval());
+                match status.read().as_deref().map(&is_current) {
+                    Ok(Some(true)) => {}
+                    // Waiting for a pending file change restart
+                    Ok(Some(false)) => continue,
+                    _ => return,
+                }
+
+                if probe.check() {
+                    if let Ok(mut status) = status.write() {
+                        if is_current(&status) == Some(true) {
+                            if let ProcessStatus::Running(process) = &mut *status {
+                                process.status = ProcessRunningStatus::Ready;
+                            }
+                        }
+                    }
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Periodically checks the health of the process once it is ready, marking it as unhealthy
+    /// after consecutive failures and restarting it if configured. Gives up once the process is
+    /// replaced.
+    fn spawn_health_checker(&self, process: &Process, health_check: HealthCheck) {
+        let state = self.clone();
+        let child = process.child.clone();
+        let is_checkable = move |status: &ProcessStatus| match status {
+            ProcessStatus::Running(process)
+                if Arc::ptr_eq(&process.child, &child) && !process.status.is_terminating() =>
+            {
+                Some(matches!(
+                    process.status,
+                    ProcessRunningStatus::Ready | ProcessRunningStatus::Unhealthy
+                ))
+            }
+            _ => None,
+        };
+
+        thread::spawn(move || {
+            let mut failures = 0;
+            loop {
+                thread::sleep(health_check.interval());
+                match state.status.read().as_deref().map(&is_checkable) {
+                    Ok(Some(true)) => {}
+                    // Not ready yet or waiting for a pending file change restart
+                    Ok(Some(false)) => continue,
+                    _ => return,
+                }
+
+                let is_healthy = health_check.check();
+                failures = if is_healthy { 0 } else { failures + 1 };
+                let is_unhealthy = failures >= health_check.retries();
+
+                if let Ok(mut status) = state.status.write() {
+                    if is_checkable(&status) != Some(true) {
+                        continue;
+                    }
+                    if let ProcessStatus::Running(process) = &mut *status {
+                        if is_healthy {
+                            process.status = ProcessRunningStatus::Ready;
+                        } else if is_unhealthy {
+                            process.status = ProcessRunningStatus::Unhealthy;
+                        }
+                    }
+                }
+
+                if is_unhealthy && health_check.should_restart() {
+                    // The restarted process gets a health checker of its own
+                    state.restart();
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Applies the restart policy once the process has exited on its own
+    fn handle_exit(&self) {
+        let exited_at = match self.status.read().as_deref() {
+            Ok(ProcessStatus::Exited { at, .. }) => *at,
+            _ => return,
+        };
+        if !self.is_restart_pending() {
+            return;
+        }
+
+        let restarts = self.restarts.load(Ordering::SeqCst);
+        thread::sleep(self.process_config.backoff.delay(restarts));
+
+        if let Ok(mut status) = self.status.write() {
+            // The process might have been launched or stopped during the backoff
+            if let ProcessStatus::Exited { at, .. } = &*status {
+                if *at == exited_at {
+                    if let Ok(process) = self.spawn() {
+                        self.restarts.fetch_add(1, Ordering::SeqCst);
+                        *status = ProcessStatus::Running(process);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Restarts the process on changes to its watched files, until it is stopped. Does nothing
+    /// when the process is already watched, e.g. when an exited process is launched again
+    fn enable_file_watching(&self) {
+        if self.is_watching.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let filter = self.process_config.watch.filter(&self.project_root);
+        ensure_watching(&filter.roots);
+        let state = self.clone();
 
         // Subscribe to the file watcher bus
         let rx = if let Ok(watcher) = FILE_WATCHER.read() {
@@ -181,30 +981,110 @@ impl ProcessState {
 
         if let Some(mut receiver) = rx {
             thread::spawn(move || {
+                // Changed paths are relative to the watched paths, which are canonicalized
+                let project_root = state
+                    .project_root
+                    .canonicalize()
+                    .unwrap_or(state.project_root.to_path_buf());
                 loop {
-                    if let Ok(WatcherEvent::FileChanged) = receiver.recv() {
-                        if let Ok(mut s) = status.write() {
+                    if let Ok(WatcherEvent::FileChanged(changes)) = receiver.recv() {
+                        let changes: Vec<_> = changes
+                            .into_iter()
+                            .filter(|x| filter.matches(&x.path))
+                            .map(|mut x| {
+                                if let Ok(path) = x.path.strip_prefix(&project_root) {
+                                    x.path = path.to_path_buf();
+                                }
+                                x
+                            })
+                            .collect();
+                        if changes.is_empty() {
+                            continue;
+                        }
+                        if let Ok(mut s) = state.status.write() {
+                            let mut is_pending = false;
                             match &mut *s {
-                                ProcessStatus::Stopped => break,
+                                ProcessStatus::Stopped => {
+                                    // Launching the process again, which needs the status
+                                    // lock, starts a new thread
+                                    state.is_watching.store(false, Ordering::SeqCst);
+                                    break;
+                                }
+                                ProcessStatus::Waiting | ProcessStatus::Exited { .. } => {}
                                 ProcessStatus::Running(process) => match &mut process.status {
-                                    ProcessRunningStatus::Stable => {
+                                    ProcessRunningStatus::Stable
+                                    | ProcessRunningStatus::Ready
+                                    | ProcessRunningStatus::Unhealthy => {
+                                        is_pending = true;
+                                        if let Ok(mut changes) = state.changes.write() {
+                                            changes.pending.clear();
+                                        }
+                                        let state = state.clone();
                                         process.status = ProcessRunningStatus::Debouncing(
                                             RestartDebounceHandler::new(
-                                                process_config.clone(),
-                                                status.clone(),
+                                                state.process_config.watch.debounce_duration(),
+                                                move || state.restart_if_debouncing(),
                                             ),
                                         );
                                     }
                                     ProcessRunningStatus::Debouncing(timer) => {
+                                        is_pending = true;
                                         timer.reset();
                                     }
-                                    ProcessRunningStatus::Restarting => {}
+                                    ProcessRunningStatus::Restarting
+                                    | ProcessRunningStatus::Stopping => {}
                                 },
                             }
+                            if let Ok(mut recorded) = state.changes.write() {
+                                recorded.record(changes, is_pending);
+                            }
                         }
                     }
                 }
             });
         }
     }
+
+    fn restart_if_debouncing(&self) {
+        let is_debouncing = matches!(
+            self.status.read().as_deref(),
+            Ok(ProcessStatus::Running(Process {
+                status: ProcessRunningStatus::Debouncing(_),
+                ..
+            }))
+        );
+        if is_debouncing {
+            self.restart();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flocks_include_their_dependencies_outside_of_the_flock() {
+        let config: AppConfig = serde_yaml::from_str(
+            "
+flocks:
+  - display_name: dev
+    processes: [web, db]
+processes:
+  - { id: other, display_name: other, command: x }
+  - { id: db, display_name: db, command: x }
+  - { id: cache, display_name: cache, command: x }
+  - { id: api, display_name: api, command: x, depends_on: [db, cache] }
+  - { id: web, display_name: web, command: x, depends_on: [api] }
+",
+        )
+        .unwrap();
+        let state = GlobalUIState::new(config);
+        let ids: Vec<_> = state.flocks[0]
+            .process_states
+            .iter()
+            .map(|x| x.process_config.id.as_str())
+            .collect();
+        assert_eq!(ids, ["web", "db", "cache", "api"]);
+    }
 }
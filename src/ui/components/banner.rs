@@ -0,0 +1,51 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Color, Style},
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Widget},
+};
+
+use crate::ui::components::texts::TITLE_STYLE;
+
+/// Message shown at the bottom of the panes, e.g. the outcome of reloading the config
+pub struct Banner {
+    title: String,
+    message: String,
+    is_error: bool,
+}
+
+impl Banner {
+    pub fn new(title: String, message: String, is_error: bool) -> Self {
+        Self {
+            title,
+            message,
+            is_error,
+        }
+    }
+}
+
+impl Widget for Banner {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let lines: Vec<_> = self.message.lines().map(Line::from).collect();
+        // Long messages such as config errors are cut to keep the panes visible
+        let height = (lines.len() as u16 + 2).min(area.height / 2);
+        let [area] = Layout::vertical([Constraint::Length(height)])
+            .flex(Flex::End)
+            .areas(area);
+        let border_style = match self.is_error {
+            true => Style::new().fg(Color::Red),
+            false => Style::new().fg(Color::Green),
+        };
+
+        Clear.render(area, buf);
+        Paragraph::new(lines)
+            .block(
+                Block::bordered()
+                    .border_style(border_style)
+                    .title(self.title)
+                    .title_style(TITLE_STYLE),
+            )
+            .render(area, buf);
+    }
+}
@@ -0,0 +1,55 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Flex, Layout, Rect},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph, Widget, Wrap},
+};
+
+use crate::ui::components::texts::TITLE_STYLE;
+
+/// Popup listing the configuration and state of a single process
+pub struct ProcessDetailView {
+    title: String,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl ProcessDetailView {
+    pub fn new(title: String, fields: Vec<(&'static str, String)>) -> Self {
+        Self { title, fields }
+    }
+}
+
+impl Widget for ProcessDetailView {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let label_width = self.fields.iter().map(|(x, _)| x.len()).max().unwrap_or(0);
+        let mut lines = vec![];
+        for (label, mut value) in self.fields {
+            if value.is_empty() {
+                value = "-".to_string();
+            }
+            // Multiline values such as scripts are aligned under the first line
+            for (i, value_line) in value.lines().enumerate() {
+                let label = if i == 0 { label } else { "" };
+                lines.push(Line::from(vec![
+                    Span::styled(
+                        format!("{:width$}  ", label, width = label_width),
+                        TITLE_STYLE,
+                    ),
+                    Span::raw(value_line.to_owned()),
+                ]));
+            }
+        }
+
+        let [area] = Layout::vertical([Constraint::Length(lines.len() as u16 + 2)])
+            .flex(Flex::Center)
+            .areas(area);
+        let [area] = Layout::horizontal([Constraint::Percentage(80)])
+            .flex(Flex::Center)
+            .areas(area);
+        Clear.render(area, buf);
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(self.title).title_style(TITLE_STYLE))
+            .render(area, buf);
+    }
+}
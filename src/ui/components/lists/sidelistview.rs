@@ -10,6 +10,7 @@ use crate::ui::components::texts::TITLE_STYLE;
 pub struct SideListView<'a> {
     title: String,
     items: Vec<ListItem<'a>>,
+    focused: bool,
 }
 impl<'a> SideListView<'a> {
     pub fn new(title: String, items: Vec<String>) -> Self {
@@ -19,8 +20,15 @@ impl<'a> SideListView<'a> {
                 .iter()
                 .map(|item| ListItem::new(item.to_owned()))
                 .collect(),
+            focused: true,
         }
     }
+
+    /// Only the focused list highlights its selection in reverse, others use bold instead
+    pub fn focused(mut self, focused: bool) -> Self {
+        self.focused = focused;
+        self
+    }
 }
 impl<'a> StatefulWidget for SideListView<'a> {
     type State = usize;
@@ -31,9 +39,13 @@ impl<'a> StatefulWidget for SideListView<'a> {
                     Block::new()
                         .borders(Borders::RIGHT)
                         .title_top(self.title)
-                        .title_style(*TITLE_STYLE),
+                        .title_style(TITLE_STYLE),
                 )
-                .highlight_style(Style::default().reversed()),
+                .highlight_style(if self.focused {
+                    Style::default().reversed()
+                } else {
+                    Style::default().bold()
+                }),
             area,
             buf,
             &mut ListState::default().with_selected(Some(*state)),
@@ -18,7 +18,7 @@ impl<T: Widget> Widget for SplitListView<T> {
     where
         Self: Sized,
     {
-        if self.widgets.len() > 0 {
+        if !self.widgets.is_empty() {
             let overall_layout = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints(
@@ -1,3 +1,7 @@
+pub mod banner;
+pub mod details;
 pub mod lists;
+pub mod pane;
 pub mod pty;
+pub mod shutdown;
 pub mod texts;
@@ -0,0 +1,22 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    widgets::{Block, Widget},
+};
+
+use crate::ui::components::pty::AutoFillPty;
+
+/// Pane of a process, which only shows output once the process has been spawned
+pub enum ProcessPane {
+    Pty(AutoFillPty),
+    Empty { title: String },
+}
+
+impl Widget for ProcessPane {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        match self {
+            ProcessPane::Pty(pty) => pty.render(area, buf),
+            ProcessPane::Empty { title } => Block::bordered().title(title).render(area, buf),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Flex, Layout, Rect},
+    text::Line,
+    widgets::{Block, Paragraph, Widget},
+};
+
+use crate::ui::components::texts::TITLE_STYLE;
+
+/// Screen shown while the running processes are being stopped before flok exits
+pub struct ShutdownView {
+    process_names: Vec<String>,
+}
+
+impl ShutdownView {
+    pub fn new(process_names: Vec<String>) -> Self {
+        Self { process_names }
+    }
+}
+
+impl Widget for ShutdownView {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut lines = vec![
+            Line::styled(
+                format!("Stopping {} processes...", self.process_names.len()),
+                TITLE_STYLE,
+            ),
+            Line::default(),
+        ];
+        lines.extend(self.process_names.into_iter().map(Line::from));
+
+        let [area] = Layout::vertical([Constraint::Length(lines.len() as u16 + 2)])
+            .flex(Flex::Center)
+            .areas(area);
+        Paragraph::new(lines)
+            .centered()
+            .block(Block::bordered())
+            .render(area, buf);
+    }
+}
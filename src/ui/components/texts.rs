@@ -1,5 +1,15 @@
-use std::cell::LazyCell;
+use std::time::Duration;
 
-use ratatui::style::{Style, Stylize};
+use ratatui::style::{Modifier, Style};
 
-pub const TITLE_STYLE: LazyCell<Style> = LazyCell::new(|| Style::new().bold());
+pub const TITLE_STYLE: Style = Style::new().add_modifier(Modifier::BOLD);
+
+/// Formats a duration to its two most significant units, e.g. "3s", "2m 5s" or "1h 4m"
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
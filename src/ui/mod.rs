@@ -1,6 +1,11 @@
 mod components;
 
-use std::time::Duration;
+use std::{
+    path::PathBuf,
+    sync::atomic::Ordering,
+    thread,
+    time::{Duration, Instant},
+};
 
 use crossterm::event::{self, Event, KeyCode, KeyModifiers};
 use ratatui::{
@@ -11,9 +16,19 @@ use ratatui::{
     prelude::*,
     widgets::Widget,
 };
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM},
+    iterator::Signals,
+};
 
-use crate::state::AppState;
+use crate::state::{AppState, MainUIFocus, ProcessState, stop_processes};
+use crate::ui::components::banner::Banner;
+use crate::ui::components::details::ProcessDetailView;
 use crate::ui::components::lists::{SideListView, SplitListView};
+use crate::ui::components::pane::ProcessPane;
+use crate::ui::components::shutdown::ShutdownView;
+use crate::ui::components::texts::format_duration;
+use crate::utils::file_watcher::ConfigWatcher;
 use crate::utils::process::ProcessStatus;
 use crate::{
     config::AppConfig,
@@ -21,9 +36,13 @@ use crate::{
 };
 use crate::{ui::components::pty::AutoFillPty, utils::process::ProcessRunningStatus};
 
-pub fn run(config: AppConfig) -> Result<(), FlokProgramError> {
+/// How long the summary of a reloaded config stays visible, errors stay until the next reload
+const NOTICE_DURATION: Duration = Duration::from_secs(5);
+
+/// Runs the TUI, reloading the config when `config_file` or the files it includes change
+pub fn run(config: AppConfig, config_file: PathBuf) -> Result<(), FlokProgramError> {
     let mut terminal = ratatui::init();
-    let app_result = App::new(config)
+    let app_result = App::new(config, config_file)
         .map_err(|e| FlokProgramError::Init(FlokProgramInitError::Unknown(e.into())))?
         .run(&mut terminal);
     ratatui::restore();
@@ -34,34 +53,131 @@ pub fn run(config: AppConfig) -> Result<(), FlokProgramError> {
 struct App {
     exit: bool,
     state: AppState,
+    signals: Signals,
+    config_file: PathBuf,
+    config_watcher: ConfigWatcher,
+    notice: Option<Notice>,
+}
+
+/// Outcome of the last config reload or launch error, shown in a banner
+struct Notice {
+    title: String,
+    message: String,
+    is_error: bool,
+    shown_at: Instant,
 }
 
 impl App {
-    fn new(config: AppConfig) -> Result<Self, anyhow::Error> {
+    fn new(config: AppConfig, config_file: PathBuf) -> Result<Self, anyhow::Error> {
         Ok(Self {
             exit: false,
-            state: AppState::new(config.clone()),
+            config_watcher: ConfigWatcher::new(config.watched_files.clone())?,
+            state: AppState::new(config),
+            signals: Signals::new([SIGINT, SIGTERM, SIGHUP])?,
+            config_file,
+            notice: None,
         })
     }
+    /// Runs until flok is exited, stopping the running processes on the way out even when the
+    /// TUI fails
     fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), FlokProgramError> {
+        let result = self.run_loop(terminal);
+        let shutdown_result = self.shutdown(terminal);
+        result.and(shutdown_result)
+    }
+
+    fn run_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<(), FlokProgramError> {
         while !self.exit {
             terminal
                 .draw(|frame| self.draw(frame))
                 .map_err(|e| FlokProgramError::Init(e.into()))?;
-            self.handle_event()
-                .map_err(|e| FlokProgramError::Execution(e.into()))?;
+            self.handle_event().map_err(FlokProgramError::Execution)?;
         }
         Ok(())
     }
 
+    /// Stops every running process before exiting, so that none of them are left orphaned.
+    /// Blocks until they are stopped, even when the shutdown screen cannot be drawn.
+    fn shutdown(&mut self, terminal: &mut DefaultTerminal) -> Result<(), FlokProgramError> {
+        let process_states = self.state.running_processes();
+        let process_names: Vec<_> = process_states
+            .iter()
+            .map(|x| x.process_config.display_name.to_owned())
+            .collect();
+
+        let handle = thread::spawn(move || stop_processes(&process_states));
+        let mut result = Ok(());
+        while !handle.is_finished() && result.is_ok() {
+            result = terminal
+                .draw(|frame| {
+                    frame.render_widget(ShutdownView::new(process_names.clone()), frame.area())
+                })
+                .map(|_| ())
+                .map_err(|e| FlokProgramError::Execution(e.into()));
+            thread::sleep(Duration::from_millis(100));
+        }
+        let _ = handle.join();
+        result
+    }
+
+    /// Shows why processes could not be launched, until dismissed or replaced by a reload
+    fn show_launch_error(&mut self, error: anyhow::Error) {
+        self.notice = Some(Notice {
+            title: "Failed to launch (Esc to dismiss)".to_string(),
+            message: error.to_string(),
+            is_error: true,
+            shown_at: Instant::now(),
+        });
+    }
+
     fn draw(&mut self, frame: &mut Frame) {
         frame.render_widget(self, frame.area());
     }
 
+    /// Reloads the config once its files changed. An invalid config is reported and the
+    /// previous config is kept.
+    fn reload_config(&mut self) {
+        if !self.config_watcher.has_changed() {
+            if self
+                .notice
+                .as_ref()
+                .is_some_and(|x| !x.is_error && x.shown_at.elapsed() >= NOTICE_DURATION)
+            {
+                self.notice = None;
+            }
+            return;
+        }
+
+        self.notice = Some(match AppConfig::from_file(&self.config_file) {
+            Ok(config) => {
+                let _ = self.config_watcher.watch(config.watched_files.clone());
+                Notice {
+                    title: "Config reloaded".to_string(),
+                    message: self.state.apply_config(config).to_string(),
+                    is_error: false,
+                    shown_at: Instant::now(),
+                }
+            }
+            Err(e) => Notice {
+                title: "Config not reloaded, the previous config is kept (Esc to dismiss)"
+                    .to_string(),
+                message: e.to_string(),
+                is_error: true,
+                shown_at: Instant::now(),
+            },
+        });
+    }
+
     fn handle_event(&mut self) -> Result<(), FlokProgramExecutionError> {
+        if self.signals.pending().next().is_some() {
+            self.exit = true;
+            return Ok(());
+        }
+        self.reload_config();
+
         if poll(Duration::from_millis(100))? {
-            match event::read()? {
-                Event::Key(k) => match (k.modifiers, k.code) {
+            if let Event::Key(k) = event::read()? {
+                match (k.modifiers, k.code) {
                     (KeyModifiers::CONTROL, KeyCode::Char('c'))
                     | (KeyModifiers::NONE, KeyCode::Char('q')) => {
                         self.exit = true;
@@ -73,11 +189,39 @@ impl App {
                         self.state.previous_item();
                     }
                     (KeyModifiers::NONE, KeyCode::Enter) => {
-                        self.state.select();
+                        if let Err(e) = self.state.select() {
+                            self.show_launch_error(e);
+                        }
+                    }
+                    (KeyModifiers::NONE, KeyCode::Tab)
+                    | (KeyModifiers::SHIFT, KeyCode::BackTab) => {
+                        self.state.switch_focus();
+                    }
+                    (KeyModifiers::NONE, KeyCode::Char('s')) => {
+                        if let Err(e) = self.state.start_process() {
+                            self.show_launch_error(e);
+                        }
+                    }
+                    (KeyModifiers::NONE, KeyCode::Char('x')) => {
+                        self.state.stop();
+                    }
+                    (KeyModifiers::NONE, KeyCode::Char('r')) => {
+                        if let Err(e) = self.state.restart_process() {
+                            self.show_launch_error(e);
+                        }
+                    }
+                    (KeyModifiers::SHIFT, KeyCode::Char('X')) => {
+                        self.state.kill_process();
+                    }
+                    (KeyModifiers::NONE, KeyCode::Char('i')) => {
+                        self.state.toggle_details();
+                    }
+                    (KeyModifiers::NONE, KeyCode::Esc) => {
+                        self.state.close_details();
+                        self.notice = None;
                     }
                     _ => {}
-                },
-                _ => {}
+                }
             }
         }
         Ok(())
@@ -90,53 +234,232 @@ impl Widget for &mut App {
             .direction(Direction::Horizontal)
             .constraints([Constraint::Percentage(20), Constraint::Fill(1)])
             .areas(area);
+        let detailed_process = self.state.detailed_process();
         match &mut self.state {
             AppState::Main(state, global_state) => {
+                let [flocks_area, processes_area] = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Fill(1), Constraint::Fill(1)])
+                    .areas(sidebar_area);
+                let active_flock = global_state.flocks.get(state.active_flock).unwrap();
+
                 SideListView::new(
                     "Flocks".to_string(),
                     global_state
                         .flocks
                         .iter()
-                        .map(|f| f.display_name.to_owned())
+                        .map(|f| {
+                            if f.is_active {
+                                format!("{} (active)", f.display_name)
+                            } else {
+                                f.display_name.to_owned()
+                            }
+                        })
                         .collect(),
                 )
-                .render(sidebar_area, buf, &mut state.active_flock);
+                .focused(state.focus == MainUIFocus::Flocks)
+                .render(flocks_area, buf, &mut state.active_flock);
 
-                let widgets = global_state
-                    .flocks
-                    .get(state.active_flock)
-                    .unwrap()
+                SideListView::new(
+                    "Processes".to_string(),
+                    active_flock
+                        .process_states
+                        .iter()
+                        .map(|x| {
+                            let status = x.status.read();
+                            let label = status.as_deref().map(status_label).unwrap_or_default();
+                            format!("{} ({})", x.process_config.display_name, label)
+                        })
+                        .collect(),
+                )
+                .focused(state.focus == MainUIFocus::Processes)
+                .render(processes_area, buf, &mut state.active_process);
+
+                let widgets = active_flock
                     .process_states
                     .iter()
                     .filter_map(|state| {
                         if let Ok(status) = state.status.read() {
-                            match *status {
+                            let (process, state_indicator) = match *status {
+                                ProcessStatus::Waiting => {
+                                    let dependencies = state
+                                        .pending_dependencies()
+                                        .iter()
+                                        .map(|x| x.process_config.display_name.to_owned())
+                                        .collect::<Vec<_>>()
+                                        .join(", ");
+                                    return Some(ProcessPane::Empty {
+                                        title: format!(
+                                            "{} [waiting for {}]",
+                                            state.process_config.display_name, dependencies
+                                        ),
+                                    });
+                                }
                                 ProcessStatus::Running(ref process) => {
+                                    let pending_changes =
+                                        state.changes.read().ok().and_then(|x| x.pending_summary());
                                     let state_indicator = match &process.status {
-                                        ProcessRunningStatus::Restarting => " [Restarting...]",
-                                        _ => "",
+                                        ProcessRunningStatus::Debouncing(_)
+                                        | ProcessRunningStatus::Restarting
+                                            if pending_changes.is_some() =>
+                                        {
+                                            pending_changes.map(|x| format!("restarting: {}", x))
+                                        }
+                                        ProcessRunningStatus::Restarting => {
+                                            Some("Restarting...".to_string())
+                                        }
+                                        ProcessRunningStatus::Stopping => {
+                                            Some("Stopping...".to_string())
+                                        }
+                                        ProcessRunningStatus::Unhealthy => {
+                                            Some("Unhealthy".to_string())
+                                        }
+                                        // Only worth showing when the readiness is probed
+                                        ProcessRunningStatus::Stable
+                                            if state.process_config.ready_when.is_some() =>
+                                        {
+                                            Some("Starting...".to_string())
+                                        }
+                                        ProcessRunningStatus::Ready
+                                            if state.process_config.ready_when.is_some() =>
+                                        {
+                                            Some("Ready".to_string())
+                                        }
+                                        _ => None,
+                                    };
+                                    (process, state_indicator)
+                                }
+                                ProcessStatus::Exited {
+                                    ref process,
+                                    code,
+                                    signal,
+                                    at,
+                                } => {
+                                    let runtime =
+                                        format_duration(at.duration_since(process.started_at));
+                                    let state_indicator = match (code, signal) {
+                                        (_, Some(signal)) => {
+                                            format!("killed by {} after {}", signal, runtime)
+                                        }
+                                        (Some(code), None) => {
+                                            format!("exited {} after {}", code, runtime)
+                                        }
+                                        (None, None) => format!("exited after {}", runtime),
                                     };
-                                    let title = format!(
-                                        "{}{}",
-                                        state.process_config.display_name, state_indicator
-                                    );
-
-                                    Some(AutoFillPty::new(
-                                        process.pty_master.clone(),
-                                        process.parser.clone(),
-                                        title,
-                                    ))
+                                    (process, Some(state_indicator))
                                 }
-                                _ => None,
+                                ProcessStatus::Stopped => return None,
+                            };
+
+                            let mut title = state.process_config.display_name.to_owned();
+                            if let Some(state_indicator) = state_indicator {
+                                title.push_str(&format!(" [{}]", state_indicator));
                             }
+                            let restarts = state.restarts.load(Ordering::SeqCst);
+                            if restarts > 0 {
+                                title.push_str(&format!(" [restarts: {}]", restarts));
+                            }
+
+                            Some(ProcessPane::Pty(AutoFillPty::new(
+                                process.pty_master.clone(),
+                                process.parser.clone(),
+                                title,
+                            )))
                         } else {
                             None
                         }
                     })
                     .collect();
 
-                SplitListView::new(widgets).render(main_area, buf)
+                SplitListView::new(widgets).render(main_area, buf);
+
+                if let Some(process_state) = detailed_process {
+                    ProcessDetailView::new(
+                        process_state.process_config.display_name.to_owned(),
+                        process_details(&process_state),
+                    )
+                    .render(main_area, buf);
+                }
+
+                if let Some(notice) = &self.notice {
+                    Banner::new(
+                        notice.title.to_owned(),
+                        notice.message.to_owned(),
+                        notice.is_error,
+                    )
+                    .render(main_area, buf);
+                }
             }
         }
     }
 }
+
+fn process_details(state: &ProcessState) -> Vec<(&'static str, String)> {
+    let config = &state.process_config;
+    let cwd = config
+        .cwd
+        .clone()
+        .or_else(|| std::env::current_dir().ok())
+        .map(|x| x.display().to_string())
+        .unwrap_or_default();
+    let env = config
+        .env
+        .iter()
+        .map(|(key, value)| match value {
+            Some(value) => format!("{}={}", key, value),
+            None => format!("unset {}", key),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let recent_changes = state
+        .changes
+        .read()
+        .map(|changes| {
+            changes
+                .recent
+                .iter()
+                .map(|x| {
+                    let ago = format_duration(x.at.elapsed());
+                    format!("{} {} ({} ago)", x.kind, x.path.display(), ago)
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default();
+    let status = state.status.read();
+    vec![
+        ("Id", config.id.to_owned()),
+        ("Command", config.command.trim_end().to_owned()),
+        ("Working directory", cwd),
+        ("Environment", env),
+        (
+            "Status",
+            status
+                .as_deref()
+                .map(status_label)
+                .unwrap_or_default()
+                .to_owned(),
+        ),
+        ("Depends on", config.depends_on.join(", ")),
+        (
+            "Restarts",
+            state.restarts.load(Ordering::SeqCst).to_string(),
+        ),
+        ("Recent changes", recent_changes),
+    ]
+}
+
+fn status_label(status: &ProcessStatus) -> &'static str {
+    match status {
+        ProcessStatus::Stopped => "stopped",
+        ProcessStatus::Waiting => "waiting",
+        ProcessStatus::Running(process) => match process.status {
+            ProcessRunningStatus::Restarting => "restarting",
+            ProcessRunningStatus::Stopping => "stopping",
+            ProcessRunningStatus::Stable => "starting",
+            ProcessRunningStatus::Unhealthy => "unhealthy",
+            _ => "running",
+        },
+        ProcessStatus::Exited { .. } => "exited",
+    }
+}
@@ -1,32 +1,36 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
 use std::mem::discriminant;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, channel};
 use std::sync::{Arc, LazyLock, Mutex, RwLock};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::anyhow;
 use bus::{Bus, BusReader};
+use glob::{MatchOptions, Pattern};
+use notify::event::ModifyKind;
 use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 
+use crate::utils::gitignore::GIT_IGNORE;
+
 pub static FILE_WATCHER: LazyLock<RwLock<FileWatcherStatus>> =
     LazyLock::new(|| RwLock::new(FileWatcherStatus::Disabled));
 
-pub fn ensure_watcher_initialized() {
-    let is_init = match FILE_WATCHER.read() {
-        Ok(state) => match *state {
-            FileWatcherStatus::Disabled => false,
-            FileWatcherStatus::Enabled(_) => true,
-        },
-        _ => false,
-    };
-
-    if !is_init {
-        if let Ok(mut status) = FILE_WATCHER.write() {
-            if *status == FileWatcherStatus::Disabled {
-                let cwd = std::env::current_dir().unwrap();
-                let file_watcher = FileWatcher::new(&cwd)
-                    .map_err(|e| anyhow!("Failed to initialize file watcher: {}", e))
-                    .unwrap();
-                *status = FileWatcherStatus::Enabled(file_watcher);
+/// Starts watching each of `roots` recursively, initializing the watcher on first use. Roots
+/// already watched, directly or through a parent, are skipped
+pub fn ensure_watching(roots: &[PathBuf]) {
+    if let Ok(mut status) = FILE_WATCHER.write() {
+        if *status == FileWatcherStatus::Disabled {
+            let file_watcher = FileWatcher::new()
+                .map_err(|e| anyhow!("Failed to initialize file watcher: {}", e))
+                .unwrap();
+            *status = FileWatcherStatus::Enabled(file_watcher);
+        }
+        if let FileWatcherStatus::Enabled(file_watcher) = &mut *status {
+            for root in roots {
+                // A root removed since the config was validated is not watched
+                let _ = file_watcher.watch(root);
             }
         }
     }
@@ -34,7 +38,88 @@ pub fn ensure_watcher_initialized() {
 
 #[derive(Clone, Debug)]
 pub enum WatcherEvent {
-    FileChanged,
+    /// Files created, modified, removed or renamed under the watched roots
+    FileChanged(Vec<FileChange>),
+}
+
+/// How a watched file changed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChangeKind {
+    Create,
+    Modify,
+    Remove,
+    Rename,
+}
+
+impl ChangeKind {
+    fn from_event(kind: &EventKind) -> Option<Self> {
+        match kind {
+            EventKind::Create(_) => Some(ChangeKind::Create),
+            EventKind::Modify(ModifyKind::Name(_)) => Some(ChangeKind::Rename),
+            EventKind::Modify(_) => Some(ChangeKind::Modify),
+            EventKind::Remove(_) => Some(ChangeKind::Remove),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            ChangeKind::Create => "created",
+            ChangeKind::Modify => "modified",
+            ChangeKind::Remove => "removed",
+            ChangeKind::Rename => "renamed",
+        };
+        write!(f, "{label}")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+    pub at: Instant,
+}
+
+/// Number of changed files remembered per process
+const RECENT_CHANGES_LIMIT: usize = 10;
+
+/// Changes to the files watched by a process, with one change per file in each list
+#[derive(Debug, Default)]
+pub struct FileChanges {
+    /// Changes that triggered the pending restart of the process
+    pub pending: Vec<FileChange>,
+    /// Latest changes, the most recent first
+    pub recent: VecDeque<FileChange>,
+}
+
+impl FileChanges {
+    /// Records `changes` in the recent changes, and in the pending ones when they trigger a
+    /// restart
+    pub fn record(&mut self, changes: Vec<FileChange>, is_pending: bool) {
+        for change in changes {
+            if is_pending {
+                match self.pending.iter_mut().find(|x| x.path == change.path) {
+                    Some(pending) => *pending = change.clone(),
+                    None => self.pending.push(change.clone()),
+                }
+            }
+            self.recent.retain(|x| x.path != change.path);
+            self.recent.push_front(change);
+        }
+        self.recent.truncate(RECENT_CHANGES_LIMIT);
+    }
+
+    /// Summary of the pending changes, e.g. `src/main.rs changed (+3 more)`
+    pub fn pending_summary(&self) -> Option<String> {
+        let first = self.pending.first()?;
+        let mut summary = format!("{} changed", first.path.display());
+        if self.pending.len() > 1 {
+            summary.push_str(&format!(" (+{} more)", self.pending.len() - 1));
+        }
+        Some(summary)
+    }
 }
 
 pub enum FileWatcherStatus {
@@ -50,40 +135,197 @@ impl PartialEq for FileWatcherStatus {
 
 pub struct FileWatcher {
     pub bus: Arc<Mutex<Bus<WatcherEvent>>>,
-    _watcher: RecommendedWatcher,
+    watcher: RecommendedWatcher,
+    roots: Vec<PathBuf>,
 }
 
 impl FileWatcher {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, notify::Error> {
+    pub fn new() -> Result<Self, notify::Error> {
         let bus = Arc::new(Mutex::new(Bus::new(100)));
         let bus_clone = bus.clone();
 
-        let mut watcher = RecommendedWatcher::new(
+        let watcher = RecommendedWatcher::new(
             move |res: Result<Event, notify::Error>| {
                 if let Ok(event) = res {
-                    // Only react to modify, create, and remove events
-                    match event.kind {
-                        EventKind::Modify(_) | EventKind::Create(_) | EventKind::Remove(_) => {
-                            if let Ok(mut b) = bus_clone.lock() {
-                                b.broadcast(WatcherEvent::FileChanged);
-                            }
+                    // Only react to modify, create, remove and rename events
+                    if let Some(kind) = ChangeKind::from_event(&event.kind) {
+                        let at = Instant::now();
+                        let changes = event
+                            .paths
+                            .into_iter()
+                            .map(|path| FileChange { path, kind, at })
+                            .collect();
+                        if let Ok(mut b) = bus_clone.lock() {
+                            b.broadcast(WatcherEvent::FileChanged(changes));
                         }
-                        _ => {}
                     }
                 }
             },
             Config::default().with_poll_interval(Duration::from_secs(1)),
         )?;
 
-        watcher.watch(path.as_ref(), RecursiveMode::Recursive)?;
-
         Ok(Self {
             bus,
-            _watcher: watcher,
+            watcher,
+            roots: vec![],
         })
     }
 
+    /// Watches `root` recursively, unless it is already watched
+    pub fn watch(&mut self, root: &Path) -> Result<(), notify::Error> {
+        if !self.roots.iter().any(|x| root.starts_with(x)) {
+            self.watcher.watch(root, RecursiveMode::Recursive)?;
+            self.roots.push(root.to_path_buf());
+        }
+        Ok(())
+    }
+
     pub fn subscribe(&self) -> BusReader<WatcherEvent> {
         self.bus.lock().unwrap().add_rx()
     }
 }
+
+/// Decides which changed files concern a process, from the roots it watches, its globs and the
+/// files ignored by git
+#[derive(Debug, Clone)]
+pub struct WatchFilter {
+    pub roots: Vec<PathBuf>,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    gitignore: bool,
+}
+
+impl WatchFilter {
+    /// The globs are expected to be validated with the config, invalid ones are ignored
+    pub fn new(
+        roots: Vec<PathBuf>,
+        include: &[String],
+        exclude: &[String],
+        gitignore: bool,
+    ) -> Self {
+        // Roots are canonicalized so that changed paths are compared with the paths that are
+        // actually watched, whichever process started watching them
+        let roots = roots
+            .into_iter()
+            .map(|x| x.canonicalize().unwrap_or(x))
+            .collect();
+        let patterns =
+            |globs: &[String]| globs.iter().filter_map(|x| Pattern::new(x).ok()).collect();
+        Self {
+            roots,
+            include: patterns(include),
+            exclude: patterns(exclude),
+            gitignore,
+        }
+    }
+
+    /// Whether `path` is under one of the roots, matches one of the include globs if any, none
+    /// of the exclude globs, and is not ignored by git unless ignored files are watched too
+    pub fn matches(&self, path: &Path) -> bool {
+        let Some(root) = self.roots.iter().find(|x| path.starts_with(x)) else {
+            return false;
+        };
+        if self.gitignore && GIT_IGNORE.is_ignored(path) {
+            return false;
+        }
+        // A watched file is matched by its name
+        let relative = match path.strip_prefix(root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative,
+            _ => path.file_name().map(Path::new).unwrap_or(path),
+        };
+        let is_included =
+            self.include.is_empty() || self.include.iter().any(|x| glob_matches(x, relative));
+        is_included && !self.exclude.iter().any(|x| glob_matches(x, relative))
+    }
+}
+
+/// Globs containing a `/` are matched against the path relative to the root, others against
+/// each of its components, so that `*.rs` matches Rust files at any depth and `target` every
+/// file in a `target` directory
+fn glob_matches(pattern: &Pattern, relative: &Path) -> bool {
+    if pattern.as_str().contains('/') {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        pattern.matches_path_with(relative, options)
+    } else {
+        relative
+            .components()
+            .any(|x| pattern.matches(&x.as_os_str().to_string_lossy()))
+    }
+}
+
+/// Time without changes to the config files before they are read again, as editors often save a
+/// file in several writes
+const CONFIG_SETTLE_DURATION: Duration = Duration::from_millis(300);
+
+/// Watches the files of the config, so that the config can be reloaded when they change
+pub struct ConfigWatcher {
+    files: Arc<RwLock<Vec<PathBuf>>>,
+    changes: Receiver<()>,
+    changed_at: Option<Instant>,
+    watcher: RecommendedWatcher,
+    watched_dirs: Vec<PathBuf>,
+}
+
+impl ConfigWatcher {
+    pub fn new(files: Vec<PathBuf>) -> Result<Self, notify::Error> {
+        let (sender, changes) = channel();
+        let watched_files: Arc<RwLock<Vec<PathBuf>>> = Arc::new(RwLock::new(vec![]));
+        let watched_files_clone = watched_files.clone();
+        let watcher = RecommendedWatcher::new(
+            move |res: Result<Event, notify::Error>| {
+                if let Ok(event) = res {
+                    let is_config = watched_files_clone
+                        .read()
+                        .is_ok_and(|files| event.paths.iter().any(|x| files.contains(x)));
+                    if is_config && !matches!(event.kind, EventKind::Access(_)) {
+                        let _ = sender.send(());
+                    }
+                }
+            },
+            Config::default().with_poll_interval(Duration::from_secs(1)),
+        )?;
+
+        let mut config_watcher = Self {
+            files: watched_files,
+            changes,
+            changed_at: None,
+            watcher,
+            watched_dirs: vec![],
+        };
+        config_watcher.watch(files)?;
+        Ok(config_watcher)
+    }
+
+    /// Replaces the watched files, e.g. when the config includes other files after a reload
+    pub fn watch(&mut self, files: Vec<PathBuf>) -> Result<(), notify::Error> {
+        // Directories are watched rather than the files, as editors often replace the file when
+        // saving and files such as the override file might not exist yet
+        for dir in files.iter().filter_map(|x| x.parent()) {
+            if !self.watched_dirs.iter().any(|x| x == dir) {
+                self.watcher.watch(dir, RecursiveMode::NonRecursive)?;
+                self.watched_dirs.push(dir.to_path_buf());
+            }
+        }
+        if let Ok(mut watched_files) = self.files.write() {
+            *watched_files = files;
+        }
+        Ok(())
+    }
+
+    /// Whether the config files changed and were left untouched since, without blocking
+    pub fn has_changed(&mut self) -> bool {
+        while self.changes.try_recv().is_ok() {
+            self.changed_at = Some(Instant::now());
+        }
+        match self.changed_at {
+            Some(changed_at) if changed_at.elapsed() >= CONFIG_SETTLE_DURATION => {
+                self.changed_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
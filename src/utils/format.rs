@@ -0,0 +1,109 @@
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+
+use crate::diagnostics::Diagnostic;
+
+/// Formats a config file can be written in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// The format of the file at `path` by its extension, or by sniffing its `content` for files
+    /// without a known extension, e.g. `.flok`
+    pub fn detect(path: &Path, content: &str) -> Self {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            Some("toml") => ConfigFormat::Toml,
+            Some("json") => ConfigFormat::Json,
+            _ => Self::sniff(content),
+        }
+    }
+
+    /// JSON starts with an object, TOML with a table header or a `key = value` line, and
+    /// anything else is read as YAML
+    fn sniff(content: &str) -> Self {
+        let Some(line) = content
+            .lines()
+            .map(str::trim)
+            .find(|x| !x.is_empty() && !x.starts_with('#'))
+        else {
+            return ConfigFormat::Yaml;
+        };
+
+        let is_table_header = line.starts_with('[') && line.ends_with(']') && !line.contains(',');
+        let is_key_value = line
+            .split_once('=')
+            .is_some_and(|(key, _)| !key.contains(':') && !key.trim().is_empty());
+        if line.starts_with('{') {
+            ConfigFormat::Json
+        } else if is_table_header || is_key_value {
+            ConfigFormat::Toml
+        } else {
+            ConfigFormat::Yaml
+        }
+    }
+
+    /// Parses `content` read from `path`, with errors pointing to where the content is invalid
+    pub fn parse<T: DeserializeOwned>(self, content: &str, path: &Path) -> Result<T, Diagnostic> {
+        match self {
+            ConfigFormat::Yaml => {
+                serde_yaml::from_str(content).map_err(|e| Diagnostic::from_yaml_error(&e, path))
+            }
+            ConfigFormat::Toml => {
+                toml::from_str(content).map_err(|e| Diagnostic::from_toml_error(&e, content, path))
+            }
+            ConfigFormat::Json => {
+                serde_json::from_str(content).map_err(|e| Diagnostic::from_json_error(&e, path))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_the_format_by_extension() {
+        let content = "{}";
+        assert_eq!(
+            ConfigFormat::detect(Path::new("flok.yml"), content),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::detect(Path::new("flok.toml"), content),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::detect(Path::new("flok.json"), "flocks: []"),
+            ConfigFormat::Json
+        );
+    }
+
+    #[test]
+    fn sniffs_the_format_without_a_known_extension() {
+        let detect = |content| ConfigFormat::detect(Path::new(".flok"), content);
+        assert_eq!(detect("{\n  \"flocks\": []\n}"), ConfigFormat::Json);
+        assert_eq!(detect("[[processes]]\nid = \"api\""), ConfigFormat::Toml);
+        assert_eq!(detect("flocks = []"), ConfigFormat::Toml);
+        assert_eq!(detect("# flok config\n\nflocks = []"), ConfigFormat::Toml);
+        assert_eq!(detect("flocks: []"), ConfigFormat::Yaml);
+        assert_eq!(detect("- a"), ConfigFormat::Yaml);
+        assert_eq!(detect("[a, b]"), ConfigFormat::Yaml);
+        assert_eq!(detect("command: a=b"), ConfigFormat::Yaml);
+        assert_eq!(detect(""), ConfigFormat::Yaml);
+    }
+
+    #[test]
+    fn parse_errors_point_to_the_file() {
+        let error = ConfigFormat::Toml
+            .parse::<toml::Table>("flocks = [", Path::new("flok.toml"))
+            .unwrap_err();
+        assert!(error.to_string().contains("flok.toml:1:"), "{error}");
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder, gitconfig_excludes_path};
+
+/// Names of the ignore files read in each directory, the later ones taking precedence
+const IGNORE_FILE_NAMES: [&str; 2] = [".gitignore", ".ignore"];
+
+pub static GIT_IGNORE: LazyLock<GitIgnore> = LazyLock::new(GitIgnore::default);
+
+/// Files ignored by git, from the `.gitignore` and `.ignore` files of the repository, its
+/// `.git/info/exclude` file and the global git excludes. Ignore files are read when a change is
+/// first seen under their directory, and read again when they change
+#[derive(Default)]
+pub struct GitIgnore {
+    /// Matchers of the ignore files of each directory
+    dirs: Mutex<HashMap<PathBuf, Arc<Gitignore>>>,
+    /// Matchers of the excludes of each repository, by the root of the repository
+    repos: Mutex<HashMap<PathBuf, Arc<Gitignore>>>,
+}
+
+impl GitIgnore {
+    /// Whether `path` is ignored by git or is in the `.git` directory, as changes to such files
+    /// are made by tools rather than by hand, e.g. build outputs or dependencies
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.forget_changed_ignore_file(path);
+        if path.components().any(|x| x.as_os_str() == ".git") {
+            return true;
+        }
+
+        // The ignore files of the closest directories take precedence, up to the root of the
+        // repository, or of the filesystem outside of a repository
+        let is_dir = path.is_dir();
+        for dir in path.ancestors().skip(1) {
+            match self
+                .dir_matcher(dir)
+                .matched_path_or_any_parents(path, is_dir)
+            {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+            if dir.join(".git").exists() {
+                let matcher = self.repo_matcher(dir);
+                return matcher
+                    .matched_path_or_any_parents(path, is_dir)
+                    .is_ignore();
+            }
+        }
+        false
+    }
+
+    /// Drops the matcher read from `path` when it is an ignore file, so that it is read again
+    fn forget_changed_ignore_file(&self, path: &Path) {
+        let Some(dir) = path.parent() else {
+            return;
+        };
+        let is_ignore_file = path
+            .file_name()
+            .is_some_and(|x| IGNORE_FILE_NAMES.iter().any(|name| x == *name));
+        if is_ignore_file {
+            if let Ok(mut dirs) = self.dirs.lock() {
+                dirs.remove(dir);
+            }
+        } else if path.ends_with(".git/info/exclude") {
+            if let (Some(root), Ok(mut repos)) = (path.ancestors().nth(3), self.repos.lock()) {
+                repos.remove(root);
+            }
+        }
+    }
+
+    fn dir_matcher(&self, dir: &Path) -> Arc<Gitignore> {
+        self.matcher(&self.dirs, dir, |builder| {
+            for name in IGNORE_FILE_NAMES {
+                let file = dir.join(name);
+                if file.is_file() {
+                    builder.add(file);
+                }
+            }
+        })
+    }
+
+    fn repo_matcher(&self, root: &Path) -> Arc<Gitignore> {
+        self.matcher(&self.repos, root, |builder| {
+            // The excludes of the repository take precedence over the global ones
+            let files = gitconfig_excludes_path()
+                .into_iter()
+                .chain([root.join(".git/info/exclude")]);
+            for file in files.filter(|x| x.is_file()) {
+                builder.add(file);
+            }
+        })
+    }
+
+    /// The matcher of `dir` in `cache`, built with the ignore files added by `add_files` when it
+    /// is not cached yet. Invalid lines of the ignore files are skipped, as git does
+    fn matcher(
+        &self,
+        cache: &Mutex<HashMap<PathBuf, Arc<Gitignore>>>,
+        dir: &Path,
+        add_files: impl FnOnce(&mut GitignoreBuilder),
+    ) -> Arc<Gitignore> {
+        if let Some(matcher) = cache.lock().ok().and_then(|x| x.get(dir).cloned()) {
+            return matcher;
+        }
+        let mut builder = GitignoreBuilder::new(dir);
+        add_files(&mut builder);
+        let matcher = Arc::new(builder.build().unwrap_or_else(|_| Gitignore::empty()));
+        if let Ok(mut cache) = cache.lock() {
+            cache.insert(dir.to_path_buf(), matcher.clone());
+        }
+        matcher
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// A git repository in a temporary directory, with the given files
+    fn repo(files: &[(&str, &str)]) -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        for (path, content) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn closer_ignore_files_take_precedence() {
+        let dir = repo(&[(".gitignore", "*.log\n"), ("sub/.gitignore", "!*.log\n")]);
+        let ignore = GitIgnore::default();
+        assert!(ignore.is_ignored(&dir.path().join("a.log")));
+        assert!(ignore.is_ignored(&dir.path().join("other/a.log")));
+        assert!(!ignore.is_ignored(&dir.path().join("sub/a.log")));
+        assert!(!ignore.is_ignored(&dir.path().join("sub/deeper/a.log")));
+    }
+
+    #[test]
+    fn whitelisted_files_are_not_ignored() {
+        let dir = repo(&[(".gitignore", "*.log\n!keep.log\n")]);
+        let ignore = GitIgnore::default();
+        assert!(ignore.is_ignored(&dir.path().join("a.log")));
+        assert!(!ignore.is_ignored(&dir.path().join("keep.log")));
+    }
+
+    #[test]
+    fn ignore_files_take_precedence_over_gitignore_files() {
+        let dir = repo(&[(".gitignore", "*.tmp\n"), (".ignore", "!kept.tmp\n")]);
+        let ignore = GitIgnore::default();
+        assert!(ignore.is_ignored(&dir.path().join("a.tmp")));
+        assert!(!ignore.is_ignored(&dir.path().join("kept.tmp")));
+    }
+
+    #[test]
+    fn git_directory_and_excludes_are_ignored() {
+        let dir = repo(&[(".git/info/exclude", "secret\n")]);
+        let ignore = GitIgnore::default();
+        assert!(ignore.is_ignored(&dir.path().join(".git/index")));
+        assert!(ignore.is_ignored(&dir.path().join("secret")));
+        assert!(!ignore.is_ignored(&dir.path().join("src/main.rs")));
+    }
+
+    #[test]
+    fn ignore_files_outside_of_the_repository_do_not_apply() {
+        let dir = repo(&[(".gitignore", "*.log\n"), ("nested/.git/HEAD", "")]);
+        let ignore = GitIgnore::default();
+        assert!(ignore.is_ignored(&dir.path().join("a.log")));
+        assert!(!ignore.is_ignored(&dir.path().join("nested/a.log")));
+    }
+
+    #[test]
+    fn changed_ignore_files_are_read_again() {
+        let dir = repo(&[(".gitignore", "*.log\n")]);
+        let ignore = GitIgnore::default();
+        assert!(ignore.is_ignored(&dir.path().join("a.log")));
+
+        fs::write(dir.path().join(".gitignore"), "").unwrap();
+        assert!(!ignore.is_ignored(&dir.path().join(".gitignore")));
+        assert!(!ignore.is_ignored(&dir.path().join("a.log")));
+    }
+}
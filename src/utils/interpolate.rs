@@ -0,0 +1,112 @@
+use anyhow::{Result, anyhow};
+
+/// Replaces `${NAME}` and `${NAME:-default}` in `template` with the value returned by `lookup`.
+/// The default is used when the value is not set or empty, and `$${` is kept as a literal `${`
+/// so that the shell can expand it instead. A variable that is not set and has no default is
+/// kept as is when `keep_unset` is set, for the shell to expand it, and is an error otherwise.
+pub fn interpolate<F>(template: &str, keep_unset: bool, mut lookup: F) -> Result<String>
+where
+    F: FnMut(&str) -> Result<Option<String>>,
+{
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            result.push_str("${");
+            rest = escaped;
+        } else if let Some(expression) = rest.strip_prefix("${") {
+            let end = expression
+                .find('}')
+                .ok_or_else(|| anyhow!("\"{}\" is missing a closing \"}}\"", rest))?;
+            let (name, default) = match expression[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&expression[..end], None),
+            };
+
+            let value = lookup(name)?.filter(|x| !x.is_empty() || default.is_none());
+            match (value, default) {
+                (Some(value), _) => result.push_str(&value),
+                (None, Some(default)) => result.push_str(default),
+                (None, None) if keep_unset => result.push_str(&rest[..end + 3]),
+                (None, None) => {
+                    return Err(anyhow!(
+                        "\"{}\" is not set, set it or give it a default with \"${{{}:-default}}\"",
+                        name,
+                        name
+                    ));
+                }
+            }
+            rest = &expression[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Result<Option<String>> {
+        Ok(match name {
+            "PORT" => Some("3000".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn replaces_variables() {
+        let result = interpolate("serve --port ${PORT} $HOME", false, lookup).unwrap();
+        assert_eq!(result, "serve --port 3000 $HOME");
+    }
+
+    #[test]
+    fn uses_the_default_when_unset_or_empty() {
+        assert_eq!(
+            interpolate("${HOST:-localhost}", false, lookup).unwrap(),
+            "localhost"
+        );
+        assert_eq!(
+            interpolate("${EMPTY:-fallback}", false, lookup).unwrap(),
+            "fallback"
+        );
+        assert_eq!(interpolate("${PORT:-80}", false, lookup).unwrap(), "3000");
+        assert_eq!(interpolate("${HOST:-}", false, lookup).unwrap(), "");
+    }
+
+    #[test]
+    fn keeps_escaped_variables_for_the_shell() {
+        let result = interpolate("echo $${PORT} ${PORT}", false, lookup).unwrap();
+        assert_eq!(result, "echo ${PORT} 3000");
+    }
+
+    #[test]
+    fn fails_on_unset_variables() {
+        let error = interpolate("${HOST}", false, lookup)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("\"HOST\" is not set"), "{error}");
+    }
+
+    #[test]
+    fn keeps_unset_variables_for_the_shell_when_asked() {
+        let result = interpolate("for f in a; do echo ${f}; done ${PORT}", true, lookup).unwrap();
+        assert_eq!(result, "for f in a; do echo ${f}; done 3000");
+        assert_eq!(interpolate("${f:-x}", true, lookup).unwrap(), "x");
+    }
+
+    #[test]
+    fn fails_on_unclosed_expressions() {
+        let error = interpolate("echo ${PORT", false, lookup)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("missing a closing"), "{error}");
+    }
+}
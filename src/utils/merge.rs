@@ -0,0 +1,119 @@
+use serde_yaml::{Mapping, Value};
+
+/// Keys identifying the items of a list, so that items with the same key are merged together
+const ITEM_KEYS: [&str; 2] = ["id", "display_name"];
+
+/// Merges `overlay` into `base`. Mappings are merged recursively, lists of items identified by
+/// one of `ITEM_KEYS` are merged item by item with new items appended, and any other value of
+/// `overlay` replaces the one of `base`.
+pub fn deep_merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => deep_merge(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Sequence(base), Value::Sequence(overlay)) if is_keyed(&overlay) => {
+            for item in overlay {
+                let existing = base
+                    .iter_mut()
+                    .find(|x| x.is_mapping() && item_key(x) == item_key(&item));
+                match existing {
+                    Some(existing) => deep_merge(existing, item),
+                    None => base.push(item),
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn is_keyed(items: &[Value]) -> bool {
+    !items.is_empty() && items.iter().all(|x| item_key(x).is_some())
+}
+
+/// The value of the first `ITEM_KEYS` key found in the item
+fn item_key(item: &Value) -> Option<(&'static str, &Value)> {
+    let mapping: &Mapping = item.as_mapping()?;
+    ITEM_KEYS
+        .iter()
+        .find_map(|key| mapping.get(*key).map(|value| (*key, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(base: &str, overlay: &str) -> Value {
+        let mut base: Value = serde_yaml::from_str(base).unwrap();
+        deep_merge(&mut base, serde_yaml::from_str(overlay).unwrap());
+        base
+    }
+
+    fn yaml(content: &str) -> Value {
+        serde_yaml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn merges_mappings_recursively() {
+        let merged = merge(
+            "env: { A: 1, B: 2 }\nwatch: true",
+            "env: { B: 3, C: 4 }\nrestart: always",
+        );
+        assert_eq!(
+            merged,
+            yaml("env: { A: 1, B: 3, C: 4 }\nwatch: true\nrestart: always")
+        );
+    }
+
+    #[test]
+    fn merges_items_by_key_and_appends_new_ones() {
+        let merged = merge(
+            "
+processes:
+  - { id: api, command: serve, env: { A: 1 } }
+  - { id: db, command: postgres }
+flocks:
+  - { display_name: dev, processes: [api, db] }
+",
+            "
+processes:
+  - { id: api, env: { B: 2 } }
+  - { id: worker, command: work }
+flocks:
+  - { display_name: mine, processes: [api] }
+",
+        );
+        assert_eq!(
+            merged,
+            yaml(
+                "
+processes:
+  - { id: api, command: serve, env: { A: 1, B: 2 } }
+  - { id: db, command: postgres }
+  - { id: worker, command: work }
+flocks:
+  - { display_name: dev, processes: [api, db] }
+  - { display_name: mine, processes: [api] }
+"
+            )
+        );
+    }
+
+    #[test]
+    fn replaces_other_values() {
+        let merged = merge(
+            "depends_on: [db, cache]\nwatch: { debounce_seconds: 2 }\nenv: { A: 1 }",
+            "depends_on: [db]\nwatch: false\nenv: { A: ~ }",
+        );
+        assert_eq!(
+            merged,
+            yaml("depends_on: [db]\nwatch: false\nenv: { A: ~ }")
+        );
+    }
+}
@@ -1,2 +1,7 @@
 pub mod file_watcher;
+pub mod format;
+pub mod gitignore;
+pub mod interpolate;
+pub mod merge;
+pub mod probe;
 pub mod process;
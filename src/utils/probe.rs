@@ -0,0 +1,331 @@
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bus::BusReader;
+use regex::Regex;
+
+use crate::config::{HealthcheckConfig, ReadyWhenConfig};
+
+/// Checks whether a process is ready according to its `ready_when` config
+pub struct ReadinessProbe {
+    config: ReadyWhenConfig,
+    log_matched: Arc<AtomicBool>,
+    done: Arc<AtomicBool>,
+}
+
+impl ReadinessProbe {
+    /// The output reader must be subscribed before the process is spawned, so that the log regex
+    /// sees every output
+    pub fn new(config: ReadyWhenConfig, mut output: BusReader<Vec<u8>>) -> Self {
+        let log_matched = Arc::new(AtomicBool::new(config.log.is_none()));
+        let done = Arc::new(AtomicBool::new(false));
+
+        if let Some(regex) = config.log.as_ref().and_then(|x| Regex::new(x).ok()) {
+            let log_matched = log_matched.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut pending = String::new();
+                // Keep reading until the probe is dropped, as the output blocks on unread readers
+                while !done.load(Ordering::SeqCst) && !log_matched.load(Ordering::SeqCst) {
+                    if let Ok(chunk) = output.recv_timeout(Duration::from_millis(200)) {
+                        pending.push_str(&strip_ansi(&String::from_utf8_lossy(&chunk)));
+                        if pending.lines().any(|line| regex.is_match(line)) {
+                            log_matched.store(true, Ordering::SeqCst);
+                        }
+                        // Only the last partial line is needed for the next chunk
+                        if let Some(end) = pending.rfind('\n') {
+                            pending.drain(..=end);
+                        }
+                    }
+                }
+            });
+        }
+
+        Self {
+            config,
+            log_matched,
+            done,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.config.interval()
+    }
+
+    /// Runs every configured probe, which might block up to the configured timeout per probe
+    pub fn check(&self) -> bool {
+        self.log_matched.load(Ordering::SeqCst)
+            && check_probes(
+                self.config.tcp_port,
+                self.config.http.as_deref(),
+                self.config.command.as_deref(),
+                self.config.timeout(),
+            )
+    }
+}
+
+impl Drop for ReadinessProbe {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Checks whether a running process still responds according to its `healthcheck` config
+pub struct HealthCheck {
+    config: HealthcheckConfig,
+}
+
+impl HealthCheck {
+    pub fn new(config: HealthcheckConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.config.interval()
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.config.retries
+    }
+
+    pub fn should_restart(&self) -> bool {
+        self.config.restart
+    }
+
+    /// Runs every configured probe, which might block up to the configured timeout per probe
+    pub fn check(&self) -> bool {
+        check_probes(
+            self.config.tcp_port,
+            self.config.http.as_deref(),
+            self.config.command.as_deref(),
+            self.config.timeout(),
+        )
+    }
+}
+
+/// Whether every given probe passes, probes that are not given are skipped
+fn check_probes(
+    tcp_port: Option<u16>,
+    http: Option<&str>,
+    command: Option<&str>,
+    timeout: Duration,
+) -> bool {
+    tcp_port.is_none_or(|port| is_port_open(port, timeout))
+        && http.is_none_or(|url| is_http_ok(url, timeout))
+        && command.is_none_or(|command| is_command_successful(command, timeout))
+}
+
+fn is_port_open(port: u16, timeout: Duration) -> bool {
+    ("localhost", port)
+        .to_socket_addrs()
+        .map(|mut addrs| addrs.any(|addr| TcpStream::connect_timeout(&addr, timeout).is_ok()))
+        .unwrap_or(false)
+}
+
+/// Minimal HTTP/1.1 GET, only plain `http://` URLs are supported
+fn is_http_ok(url: &str, timeout: Duration) -> bool {
+    let Some(url) = url.strip_prefix("http://") else {
+        return false;
+    };
+    let (authority, path) = match url.find('/') {
+        Some(i) => (&url[..i], &url[i..]),
+        None => (url, "/"),
+    };
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let Ok(addrs) = address.to_socket_addrs() else {
+        return false;
+    };
+    addrs.into_iter().any(|addr| {
+        let Ok(mut stream) = TcpStream::connect_timeout(&addr, timeout) else {
+            return false;
+        };
+        let _ = stream.set_read_timeout(Some(timeout));
+        let _ = stream.set_write_timeout(Some(timeout));
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            path, authority
+        );
+        if stream.write_all(request.as_bytes()).is_err() {
+            return false;
+        }
+
+        // Only the status line is needed, e.g. "HTTP/1.1 200 OK"
+        let mut response = vec![];
+        let mut buffer = [0; 256];
+        while !response.contains(&b'\n') {
+            match stream.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => response.extend_from_slice(&buffer[..n]),
+            }
+        }
+        String::from_utf8_lossy(&response)
+            .split_whitespace()
+            .nth(1)
+            .and_then(|x| x.parse::<u16>().ok())
+            .is_some_and(|x| (200..300).contains(&x))
+    })
+}
+
+fn is_command_successful(command: &str, timeout: Duration) -> bool {
+    let shell = std::env::var("SHELL").unwrap_or("sh".to_string());
+    let Ok(mut child) = Command::new(shell)
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return false;
+    };
+
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return status.success(),
+            Ok(None) if start.elapsed() < timeout => thread::sleep(Duration::from_millis(50)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return false;
+            }
+        }
+    }
+}
+
+/// Removes terminal escape sequences, so that the log regex only sees the text
+fn strip_ansi(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            if chars.peek() == Some(&'[') {
+                chars.next();
+                // CSI sequences end with a byte in the range @ to ~
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            } else {
+                // Other sequences have intermediate bytes, e.g. `(` to select a charset, then
+                // a final byte
+                while chars.next_if(|c| (' '..='/').contains(c)).is_some() {}
+                chars.next();
+            }
+        } else if c != '\r' {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use bus::Bus;
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Serves a single request with `status_line` on a free port, returning the port
+    fn serve_once(status_line: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buffer = [0; 256];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buffer[..n]),
+                }
+            }
+            let response = format!("{status_line}\r\nContent-Length: 0\r\n\r\n");
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        port
+    }
+
+    /// A port nothing listens on, as the listener bound to it is dropped right away
+    fn closed_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn http_probe_passes_on_2xx() {
+        let port = serve_once("HTTP/1.1 204 No Content");
+        assert!(is_http_ok(
+            &format!("http://127.0.0.1:{port}/health"),
+            TIMEOUT
+        ));
+    }
+
+    #[test]
+    fn http_probe_fails_on_non_2xx() {
+        let port = serve_once("HTTP/1.1 503 Service Unavailable");
+        assert!(!is_http_ok(&format!("http://127.0.0.1:{port}"), TIMEOUT));
+    }
+
+    #[test]
+    fn http_probe_fails_on_closed_port() {
+        let port = closed_port();
+        assert!(!is_http_ok(&format!("http://127.0.0.1:{port}/"), TIMEOUT));
+    }
+
+    #[test]
+    fn tcp_probe_checks_the_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(is_port_open(port, TIMEOUT));
+        drop(listener);
+        assert!(!is_port_open(port, TIMEOUT));
+    }
+
+    #[test]
+    fn strip_ansi_keeps_the_text() {
+        assert_eq!(
+            strip_ansi("\x1b[1;32mready\x1b[0m on \x1b(Bport 3000\r"),
+            "ready on port 3000"
+        );
+        assert_eq!(strip_ansi("plain text"), "plain text");
+    }
+
+    #[test]
+    fn log_probe_matches_across_chunks() {
+        let mut output = Bus::new(10);
+        let config = ReadyWhenConfig {
+            tcp_port: None,
+            http: None,
+            log: Some("listening on \\d+".to_string()),
+            command: None,
+            interval_seconds: 0.1,
+            timeout_seconds: 1.0,
+        };
+        let probe = ReadinessProbe::new(config, output.add_rx());
+        assert!(!probe.check());
+
+        for chunk in ["starting\nlist", "ening o", "n 30", "00\n"] {
+            output.broadcast(chunk.as_bytes().to_vec());
+        }
+        let start = Instant::now();
+        while !probe.check() && start.elapsed() < TIMEOUT {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(probe.log_matched.load(Ordering::SeqCst));
+    }
+}
@@ -1,21 +1,42 @@
 use std::io::{Read, Write};
 use std::mem::discriminant;
+use std::path::Path;
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 use std::time::{Duration, Instant};
 
 use anyhow::{Result, anyhow};
-use nix::sys::signal::{Signal, kill};
+use bus::Bus;
+use nix::sys::signal::{Signal, killpg};
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
 use nix::unistd::Pid;
 use portable_pty::{CommandBuilder, PtySize, native_pty_system};
 use tempfile::NamedTempFile;
 
-use crate::config::ProcessConfig;
+use crate::config::EnvConfig;
+
+/// Broadcasts the raw output of a process to any interested reader, e.g. the headless printer
+pub type ProcessOutput = Arc<Mutex<Bus<Vec<u8>>>>;
+
+/// Time given to a process to exit after SIGTERM before it is killed
+const TERMINATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a running process is checked for exit
+const REAP_INTERVAL: Duration = Duration::from_millis(200);
 
 #[derive(Clone)]
 pub enum ProcessStatus {
     Stopped,
+    /// Launched, but waiting for its dependencies to be ready before it is started
+    Waiting,
     Running(Process),
+    /// The process exited on its own. The last process is kept so that its output stays visible.
+    Exited {
+        process: Process,
+        code: Option<i32>,
+        signal: Option<Signal>,
+        at: Instant,
+    },
 }
 
 impl PartialEq for ProcessStatus {
@@ -30,10 +51,18 @@ pub struct Process {
     pub pty_master: Arc<Mutex<Box<dyn portable_pty::MasterPty + Send>>>,
     pub parser: Arc<RwLock<vt100::Parser>>,
     pub status: ProcessRunningStatus,
+    pub started_at: Instant,
 }
 
 impl Process {
-    pub fn new(command: String) -> Result<Self> {
+    /// Spawns the command in `cwd`, or in the current directory when not given. `env` is set on
+    /// top of the environment of flok, where a null value unsets the variable.
+    pub fn new(
+        command: String,
+        cwd: Option<&Path>,
+        env: &EnvConfig,
+        output: ProcessOutput,
+    ) -> Result<Self> {
         // Launch the process using PTY for proper interactive support
         let pty_system = native_pty_system();
         let pair = pty_system
@@ -54,7 +83,16 @@ impl Process {
         let shell = std::env::var("SHELL").unwrap_or("sh".to_string());
         let mut cmd = CommandBuilder::new(shell);
         cmd.arg(script_path);
-        cmd.cwd(std::env::current_dir()?);
+        match cwd {
+            Some(cwd) => cmd.cwd(cwd),
+            None => cmd.cwd(std::env::current_dir()?),
+        }
+        for (key, value) in env.iter() {
+            match value {
+                Some(value) => cmd.env(key, value),
+                None => cmd.env_remove(key),
+            }
+        }
 
         let child = pair
             .slave
@@ -82,6 +120,9 @@ impl Process {
                 }
                 // Feed the output to the VT100 parser
                 parser_clone.write().unwrap().process(&buffer[..bytes_read]);
+                if let Ok(mut output) = output.lock() {
+                    output.broadcast(buffer[..bytes_read].to_vec());
+                }
             }
         });
 
@@ -90,15 +131,139 @@ impl Process {
             pty_master: Arc::new(Mutex::new(pair.master)),
             parser,
             status: ProcessRunningStatus::Stable,
+            started_at: Instant::now(),
         })
     }
+
+    /// Watches for the process to exit on its own and moves `status` to `ProcessStatus::Exited`
+    /// when it does, then calls `on_exit`. Exits caused by a stop or a restart are left to the
+    /// caller of `terminate`.
+    pub fn spawn_reaper<F>(&self, status: Arc<RwLock<ProcessStatus>>, on_exit: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let child = self.child.clone();
+        let pid = match child.read().unwrap().process_id() {
+            Some(pid) => Pid::from_raw(pid as i32),
+            None => return,
+        };
+
+        thread::spawn(move || {
+            loop {
+                thread::sleep(REAP_INTERVAL);
+
+                let Ok(mut s) = status.write() else {
+                    return;
+                };
+                let process = match &*s {
+                    ProcessStatus::Running(process)
+                        if Arc::ptr_eq(&process.child, &child)
+                            && !process.status.is_terminating() =>
+                    {
+                        process.clone()
+                    }
+                    // Stopped, being terminated or replaced by a new process
+                    _ => return,
+                };
+
+                let (code, signal) = match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+                    Ok(WaitStatus::Exited(_, code)) => (Some(code), None),
+                    Ok(WaitStatus::Signaled(_, signal, _)) => (None, Some(signal)),
+                    Ok(_) => continue,
+                    // Already reaped elsewhere, the exit status is lost
+                    Err(_) => (None, None),
+                };
+                *s = ProcessStatus::Exited {
+                    process,
+                    code,
+                    signal,
+                    at: Instant::now(),
+                };
+                break;
+            }
+            on_exit();
+        });
+    }
+
+    /// Stops the process gracefully with SIGTERM, falling back to SIGKILL once
+    /// `TERMINATE_TIMEOUT` has passed. Blocks until the process is gone.
+    ///
+    /// The whole process group is signalled so that the processes started by the shell script
+    /// (e.g. `npm run dev` and its `node` child) are stopped along with the shell.
+    pub fn terminate(&self) {
+        let Some(pgid) = self.pgid() else {
+            return;
+        };
+
+        // Send SIGTERM
+        let _ = killpg(pgid, Signal::SIGTERM);
+
+        // Wait for every process in the group to exit with timeout
+        let start = Instant::now();
+        loop {
+            // Reap the shell, otherwise it stays in the group as a zombie
+            let _ = self.child.write().unwrap().try_wait();
+            if !is_group_alive(pgid) {
+                return;
+            }
+
+            // Still running, check timeout
+            if start.elapsed() >= TERMINATE_TIMEOUT {
+                // Timeout exceeded, send SIGKILL
+                let _ = killpg(pgid, Signal::SIGKILL);
+                // Wait a bit for SIGKILL to take effect
+                thread::sleep(Duration::from_millis(100));
+                let _ = self.child.write().unwrap().try_wait();
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Kills the whole process group right away with SIGKILL, for processes that are stuck
+    pub fn kill(&self) {
+        let Some(pgid) = self.pgid() else {
+            return;
+        };
+
+        let _ = killpg(pgid, Signal::SIGKILL);
+        // Wait a bit for SIGKILL to take effect
+        thread::sleep(Duration::from_millis(100));
+        let _ = self.child.write().unwrap().try_wait();
+    }
+
+    /// Whether any process of the group is still alive, e.g. one started in the background by
+    /// the shell after the shell has exited
+    pub fn is_group_alive(&self) -> bool {
+        self.pgid().is_some_and(is_group_alive)
+    }
+
+    fn pgid(&self) -> Option<Pid> {
+        // The process group ID is the same as the process ID as portable-pty spawns the shell as
+        // a session leader
+        self.child
+            .read()
+            .unwrap()
+            .process_id()
+            .map(|pid| Pid::from_raw(pid as i32))
+    }
+}
+
+fn is_group_alive(pgid: Pid) -> bool {
+    // Signal 0 only checks whether any process of the group can still be signalled
+    killpg(pgid, None).is_ok()
 }
 
 #[derive(Clone)]
 pub enum ProcessRunningStatus {
     Stable,
+    /// The readiness probes of the process have passed
+    Ready,
+    /// The health check of the process failed for the configured number of retries
+    Unhealthy,
     Debouncing(RestartDebounceHandler),
     Restarting,
+    Stopping,
 }
 
 impl PartialEq for ProcessRunningStatus {
@@ -107,16 +272,30 @@ impl PartialEq for ProcessRunningStatus {
     }
 }
 
+impl ProcessRunningStatus {
+    /// Whether the process is being terminated on purpose, so that its exit is expected
+    pub fn is_terminating(&self) -> bool {
+        matches!(
+            self,
+            ProcessRunningStatus::Restarting | ProcessRunningStatus::Stopping
+        )
+    }
+}
+
 #[derive(Clone)]
 pub struct RestartDebounceHandler {
     started_at: Arc<RwLock<Instant>>,
 }
 
 impl RestartDebounceHandler {
-    pub fn new(process_config: Arc<ProcessConfig>, status: Arc<RwLock<ProcessStatus>>) -> Self {
+    /// Calls `on_expire` once `duration` has passed without any `reset`
+    pub fn new<F>(duration: Duration, on_expire: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
         let started_at = Arc::new(RwLock::new(Instant::now()));
         let s = Self { started_at };
-        s.spawn_handler_thread(process_config, status);
+        s.spawn_handler_thread(duration, on_expire);
         s
     }
 
@@ -126,115 +305,47 @@ impl RestartDebounceHandler {
         }
     }
 
-    fn spawn_handler_thread(
-        &self,
-        process_config: Arc<ProcessConfig>,
-        status: Arc<RwLock<ProcessStatus>>,
-    ) {
-        let duration = process_config.watch.debounce_duration();
-        fn is_restartable(status: &ProcessStatus) -> bool {
-            if let ProcessStatus::Running(process) = status {
-                if let ProcessRunningStatus::Debouncing(_) = process.status {
-                    return true;
-                }
-            }
-            false
-        }
-
-        let handler = move || {
-            let restartable = if let Ok(s) = status.read() {
-                is_restartable(&*s)
-            } else {
-                false
-            };
-
-            if restartable {
-                if let Ok(mut s) = status.write() {
-                    if is_restartable(&*s) {
-                        if let ProcessStatus::Running(process) = &mut *s {
-                            process.status = ProcessRunningStatus::Restarting;
-
-                            let process_config = process_config.clone();
-                            let child = process.child.clone();
-                            let status = status.clone();
-                            std::thread::spawn(move || {
-                                let restart = move |status: Arc<RwLock<ProcessStatus>>| {
-                                    if let Ok(mut s) = status.write() {
-                                        *s = ProcessStatus::Running(
-                                            Process::new(process_config.command.to_owned())
-                                                .unwrap(),
-                                        );
-                                    }
-                                };
-                                // Get the process ID
-                                let pid = {
-                                    let child_lock = child.read().unwrap();
-                                    match child_lock.process_id() {
-                                        Some(pid) => pid,
-                                        None => {
-                                            // No PID, notify completion and exit
-                                            restart(status);
-                                            return;
-                                        }
-                                    }
-                                };
-                                let nix_pid = Pid::from_raw(pid as i32);
-
-                                // Send SIGTERM
-                                let _ = kill(nix_pid, Signal::SIGTERM);
-
-                                // Wait for process to exit with timeout
-                                let start = Instant::now();
-                                loop {
-                                    let exit_status = {
-                                        let mut child_lock = child.write().unwrap();
-                                        child_lock.try_wait()
-                                    };
-
-                                    match exit_status {
-                                        Ok(Some(_)) => {
-                                            // Process exited, notify completion
-                                            let _ = restart(status);
-                                            return;
-                                        }
-                                        Ok(None) => {
-                                            // Still running, check timeout
-                                            if start.elapsed() >= Duration::from_secs(5) {
-                                                // Timeout exceeded, send SIGKILL
-                                                let _ = kill(nix_pid, Signal::SIGKILL);
-                                                // Wait a bit for SIGKILL to take effect
-                                                std::thread::sleep(Duration::from_millis(100));
-                                                let _ = child.write().unwrap().try_wait();
-                                                // Notify completion after SIGKILL
-                                                let _ = restart(status);
-                                                return;
-                                            }
-                                            std::thread::sleep(Duration::from_millis(50));
-                                        }
-                                        Err(_) => {
-                                            // Error checking, assume exited, notify completion
-                                            let _ = restart(status);
-                                            return;
-                                        }
-                                    }
-                                }
-                            });
-                        }
-                    }
-                }
-            }
-        };
-
+    fn spawn_handler_thread<F>(&self, duration: Duration, on_expire: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
         let started_at = self.started_at.clone();
         thread::spawn(move || {
+            // A reset only moves the expiry later, so sleeping until the current expiry never
+            // expires too early
             loop {
-                if let Ok(started_at) = started_at.read() {
-                    if started_at.elapsed() >= duration {
-                        handler();
-                        break;
-                    }
+                let remaining = match started_at.read() {
+                    Ok(started_at) => duration.saturating_sub(started_at.elapsed()),
+                    Err(_) => Duration::ZERO,
+                };
+                if remaining.is_zero() {
+                    break;
                 }
+                thread::sleep(remaining);
             }
+            on_expire();
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn debounce_expires_once_after_the_last_reset() {
+        let (sender, receiver) = mpsc::channel();
+        let started = Instant::now();
+        let mut handler = RestartDebounceHandler::new(Duration::from_millis(200), move || {
+            sender.send(Instant::now()).unwrap()
+        });
+        thread::sleep(Duration::from_millis(100));
+        handler.reset();
+
+        let expired_at = receiver.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert!(expired_at - started >= Duration::from_millis(300));
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}